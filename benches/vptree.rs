@@ -0,0 +1,110 @@
+/* 
+ * VPTree build and query times, on the bundled named colors and on uniformly random points
+ * in the unit cube. Nearest queries are also timed as a linear scan, the baseline the tree has
+ * to beat.
+ *
+ *      cargo bench --bench vptree
+ * */
+
+use std::path::Path;
+
+use criterion::{criterion_group, criterion_main, Criterion, BenchmarkId, black_box};
+use rand::{Rng, SeedableRng, rngs::SmallRng};
+
+use blush::util::{color::{Color, ColorDB, NamedColor, load_db}, vptree::{VPTree, MetricPoint}};
+
+#[derive(Clone, Copy, Debug)]
+struct Point([f32; 3]);
+
+impl MetricPoint for Point {
+    type Dist = f32;
+
+    fn dist(from: &Self, to: &Self) -> Self::Dist {
+        let [a, b, c] = from.0;
+        let [x, y, z] = to.0;
+        ((a - x) * (a - x) + (b - y) * (b - y) + (c - z) * (c - z)).sqrt()
+    }
+}
+
+fn random_points(rng: &mut SmallRng, n: usize) -> Vec<Point> {
+    (0..n).map(|_| Point([rng.gen(), rng.gen(), rng.gen()])).collect()
+}
+
+fn random_colors(rng: &mut SmallRng, n: usize) -> Vec<NamedColor> {
+    (0..n)
+        .map(|_| NamedColor::new(Color::from_rgb(rng.gen(), rng.gen(), rng.gen()), String::new()))
+        .collect()
+}
+
+fn linear_nearest<'a, T: MetricPoint<Dist = f32>>(points: &'a [T], query: &T) -> &'a T {
+    points.iter()
+        .min_by(|a, b| MetricPoint::dist(*a, query).total_cmp(&MetricPoint::dist(*b, query)))
+        .unwrap()
+}
+
+fn named_colors(c: &mut Criterion) {
+    let db: ColorDB = load_db(Path::new("res/colors.json")).unwrap();
+    let entries: Vec<NamedColor> = serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(
+            &std::fs::read_to_string("res/colors.json").unwrap()).unwrap()
+        .into_iter()
+        .map(|(hex, name)| NamedColor::new(Color::from_hex(&hex), name.as_str().unwrap().to_owned()))
+        .collect();
+    let queries = random_colors(&mut SmallRng::seed_from_u64(2), 1000);
+
+    let mut group = c.benchmark_group("colors.json");
+    group.bench_function("build", |b| b.iter(|| VPTree::build(black_box(entries.clone()))));
+    group.bench_function("nearest x1000", |b| b.iter(|| {
+        for query in &queries {
+            black_box(db.nearest(query));
+        }
+    }));
+    group.bench_function("linear nearest x1000", |b| b.iter(|| {
+        for query in &queries {
+            black_box(linear_nearest(&entries, query));
+        }
+    }));
+    group.bench_function("k_nearest(5) x1000", |b| b.iter(|| {
+        for query in &queries {
+            black_box(db.k_nearest(query, 5));
+        }
+    }));
+    group.finish();
+}
+
+fn synthetic(c: &mut Criterion) {
+    let mut rng = SmallRng::seed_from_u64(0xface);
+    let queries = random_points(&mut rng, 1000);
+
+    let mut group = c.benchmark_group("synthetic");
+    group.sample_size(10);
+
+    for n in [10_000, 1_000_000] {
+        let points = random_points(&mut rng, n);
+        group.bench_with_input(BenchmarkId::new("build", n), &points, |b, points| {
+            b.iter(|| VPTree::build(black_box(points.clone())))
+        });
+
+        group.bench_with_input(BenchmarkId::new("linear nearest x1000", n), &points, |b, points| b.iter(|| {
+            for query in &queries {
+                black_box(linear_nearest(points, query));
+            }
+        }));
+
+        let tree = VPTree::build(points);
+        group.bench_with_input(BenchmarkId::new("nearest x1000", n), &tree, |b, tree| b.iter(|| {
+            for query in &queries {
+                black_box(tree.nearest(query));
+            }
+        }));
+        group.bench_with_input(BenchmarkId::new("k_nearest(10) x1000", n), &tree, |b, tree| b.iter(|| {
+            for query in &queries {
+                black_box(tree.k_nearest(query, 10));
+            }
+        }));
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, named_colors, synthetic);
+criterion_main!(benches);
@@ -0,0 +1,373 @@
+/* 
+ * Headless companion to the blush GUI, for build pipelines.
+ *
+ *      blush-cli name <color> [--db colors.json]...
+ *      blush-cli build-db <out> --db colors.json...
+ *      blush-cli convert <in> <out>
+ *      blush-cli shades <color> [--axis luminance|chroma|hue] [--count 10] [--space cielch|oklch|hsluv]
+ *      blush-cli contrast <text> <background> [--target aa]
+ *      blush-cli fix-contrast <in> <out> --background <color> [--target aa]
+ *
+ * Every command takes `--format text|json`. Colors are in any CSS color syntax. `fix-contrast`
+ * leaves cells derived from another cell alone, fixing them would break their relation; they are
+ * listed with their source instead.
+ * */
+
+use std::{env, fs, path::{Path, PathBuf}, process::ExitCode};
+
+use serde_json::json;
+
+use blush::{formats, util::{color::{Color, ColorDB, ContrastLevel, build_db, bundled_db, db_to_bytes, 
+                                          quantize_color, fix_contrast, shades, Lerp, luminance_lerp, 
+                                          chroma_lerp, hue_lerp},
+                           dictionary::Dictionary, space::WorkingSpace, notation::ColorParseError}};
+
+const USAGE: &str = "usage:
+    blush-cli name <color> [--db <dictionary>]...
+    blush-cli build-db <out> --db <dictionary>...
+    blush-cli convert <in> <out>
+    blush-cli shades <color> [--axis luminance|chroma|hue] [--count <n>] [--space <space>]
+    blush-cli contrast <text> <background> [--target <level>]
+    blush-cli fix-contrast <in> <out> --background <color> [--target <level>]
+
+options:
+    --format text|json    output format, text by default
+    --db <dictionary>     a .json, .csv or .gpl named color dictionary; later ones take priority,
+                          the bundled names are used without any
+    --target <level>      aa, aaa, aa-large, aaa-large or a contrast ratio, aa by default
+    --space <space>       cielch, oklch or hsluv, the space shades are taken in, cielch by default
+
+colors are written as in CSS: #rgb, #rrggbb, rgb(), hsl(), hwb(), lab(), lch(), oklch() or a name";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Output {
+    Text,
+    Json,
+}
+
+struct Args {
+    positional: Vec<String>,
+    options: Vec<(String, String)>,
+}
+
+impl Args {
+    fn parse(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut positional = vec![];
+        let mut options = vec![];
+        let mut args = args;
+        while let Some(arg) = args.next() {
+            if let Some(key) = arg.strip_prefix("--") {
+                let value = args.next().ok_or(format!("missing value for --{}", key))?;
+                options.push((key.to_owned(), value));
+            } else {
+                positional.push(arg);
+            }
+        }
+        Ok(Args { positional, options })
+    }
+
+    fn option(&self, key: &str) -> Option<&str> {
+        self.options.iter().rev().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    // Every value of a repeated option, in order.
+    fn options(&self, key: &str) -> Vec<&str> {
+        self.options.iter().filter(|(k, _)| k == key).map(|(_, v)| v.as_str()).collect()
+    }
+
+    fn positional(&self, idx: usize, what: &str) -> Result<&str, String> {
+        self.positional.get(idx).map(String::as_str).ok_or(format!("missing {}", what))
+    }
+}
+
+// Any CSS color: hex, a color function or a name.
+fn parse_color(text: &str) -> Result<Color, String> {
+    text.parse().map_err(|err: ColorParseError| err.to_string())
+}
+
+// The `--db` dictionaries layered in order.
+fn dictionaries(args: &Args) -> Result<(Vec<&str>, ColorDB), String> {
+    let paths = args.options("db");
+    if paths.is_empty() {
+        return Err("missing --db".to_owned());
+    }
+    let dictionaries = paths.iter()
+        .enumerate()
+        .map(|(priority, path)| Dictionary::load(Path::new(path), priority as i32)
+             .map_err(|err| format!("{}: {}", path, err)))
+        .collect::<Result<Vec<Dictionary>, String>>()?;
+    Ok((paths, build_db(&dictionaries)))
+}
+
+fn name(args: &Args, output: Output) -> Result<String, String> {
+    let color = parse_color(args.positional(1, "color")?)?;
+    let db: ColorDB = if args.options("db").is_empty() {
+        bundled_db()
+    } else {
+        dictionaries(args)?.1
+    };
+    let named = quantize_color(&db, color);
+    let source = named.source().map_or("", |source| source.name());
+
+    Ok(match output {
+        Output::Text => format!("{}\t{}\t{}", named.name(), named.color().to_hex(), source),
+        Output::Json => json!({
+            "query": color.to_hex(),
+            "name": named.name(),
+            "hex": named.color().to_hex(),
+            "source": source,
+        }).to_string(),
+    })
+}
+
+// Regenerate the bundled database with `blush-cli build-db res/colors.bin --db res/colors.json`.
+fn write_db(args: &Args, output: Output) -> Result<String, String> {
+    let out = PathBuf::from(args.positional(1, "output file")?);
+    let (paths, db) = dictionaries(args)?;
+    let bytes = db_to_bytes(&db).map_err(|err| err.to_string())?;
+    fs::write(&out, &bytes).map_err(|err| format!("{}: {}", out.display(), err))?;
+
+    Ok(match output {
+        Output::Text => format!("{} -> {} ({} colors, {} bytes)", paths.join(", "), out.display(), db.len(), bytes.len()),
+        Output::Json => json!({
+            "from": paths,
+            "to": out.display().to_string(),
+            "colors": db.len(),
+            "bytes": bytes.len(),
+        }).to_string(),
+    })
+}
+
+fn convert(args: &Args, output: Output) -> Result<String, String> {
+    let from = PathBuf::from(args.positional(1, "input file")?);
+    let to = PathBuf::from(args.positional(2, "output file")?);
+
+    let map = formats::load(&from).map_err(|err| format!("{}: {}", from.display(), err))?;
+    formats::save(&map, &to).map_err(|err| format!("{}: {}", to.display(), err))?;
+
+    Ok(match output {
+        Output::Text => format!("{} -> {}", from.display(), to.display()),
+        Output::Json => json!({
+            "from": from.display().to_string(),
+            "to": to.display().to_string(),
+            "colors": map.cells().len(),
+        }).to_string(),
+    })
+}
+
+fn shades_of(args: &Args, output: Output) -> Result<String, String> {
+    let color = parse_color(args.positional(1, "color")?)?;
+    let space = args.option("space").unwrap_or("cielch");
+    let space = WorkingSpace::from_name(space).ok_or(format!("unknown space: {}", space))?;
+    let lerp: Lerp = match args.option("axis").unwrap_or("luminance") {
+        "luminance" => luminance_lerp(space, 0.0, 1.0),
+        "chroma" => chroma_lerp(space, 0.0, 1.0),
+        "hue" => hue_lerp(space, 0.0, 1.0),
+        axis => return Err(format!("unknown axis: {}", axis)),
+    };
+    let count: usize = args.option("count").unwrap_or("10")
+        .parse()
+        .map_err(|_| "count must be a positive number".to_owned())?;
+    if count == 0 {
+        return Err("count must be a positive number".to_owned());
+    }
+
+    let (shades, index) = shades(color, count, &lerp);
+    let hexes: Vec<String> = shades.iter().map(|shade| shade.to_hex()).collect();
+    // gamut mapped shades
+    let out_of_gamut: Vec<usize> = (0..shades.len()).filter(|&i| !shades[i].in_gamut()).collect();
+
+    Ok(match output {
+        Output::Text => hexes.iter()
+            .enumerate()
+            .map(|(i, hex)| {
+                let mut line = hex.clone();
+                if out_of_gamut.contains(&i) {
+                    line.push_str(" (out of gamut)");
+                }
+                if i == index {
+                    line.push_str(" *");
+                }
+                line
+            })
+            .collect::<Vec<String>>()
+            .join("\n"),
+        Output::Json => json!({ "shades": hexes, "index": index, "out_of_gamut": out_of_gamut }).to_string(),
+    })
+}
+
+// The contrast ratio asked for by `--target`.
+fn target_ratio(args: &Args) -> Result<f32, String> {
+    let target = args.option("target").unwrap_or("aa");
+    let level = match target {
+        "aa" => ContrastLevel::Aa,
+        "aaa" => ContrastLevel::Aaa,
+        "aa-large" => ContrastLevel::AaLarge,
+        "aaa-large" => ContrastLevel::AaaLarge,
+        ratio => return ratio.parse::<f32>()
+            .ok()
+            .filter(|ratio| (1.0..=21.0).contains(ratio))
+            .ok_or(format!("unknown contrast target: {}", target)),
+    };
+    Ok(level.min_ratio())
+}
+
+fn contrast(args: &Args, output: Output) -> Result<String, String> {
+    let text = parse_color(args.positional(1, "text color")?)?;
+    let background = parse_color(args.positional(2, "background color")?)?;
+    let target = target_ratio(args)?;
+
+    let ratio = text.contrast_ratio(background);
+    let apca = text.apca_contrast(background);
+    let fixed = if ratio >= target { Some(text) } else { fix_contrast(text, background, target) };
+    let fixed_hex = fixed.map(Color::to_hex);
+
+    Ok(match output {
+        Output::Text => {
+            let mut lines = vec![format!("ratio\t{:.2}", ratio), format!("apca\t{:.1}", apca)];
+            for level in ContrastLevel::ALL {
+                let verdict = if level.passes(ratio) { "pass" } else { "fail" };
+                lines.push(format!("{}\t{}", level.name(), verdict));
+            }
+            lines.push(format!("fixed\t{}", fixed_hex.as_deref().unwrap_or("none")));
+            lines.join("\n")
+        }
+        Output::Json => json!({
+            "text": text.to_hex(),
+            "background": background.to_hex(),
+            "ratio": ratio,
+            "apca": apca,
+            "levels": ContrastLevel::ALL.iter()
+                .map(|level| (level.name().to_owned(), json!(level.passes(ratio))))
+                .collect::<serde_json::Map<String, serde_json::Value>>(),
+            "fixed": fixed_hex,
+        }).to_string(),
+    })
+}
+
+// Fix every cell of a palette against one background.
+fn fix_contrast_of(args: &Args, output: Output) -> Result<String, String> {
+    let from = PathBuf::from(args.positional(1, "input file")?);
+    let to = PathBuf::from(args.positional(2, "output file")?);
+    let background = parse_color(args.option("background").ok_or("missing --background")?)?;
+    let target = target_ratio(args)?;
+
+    let mut map = formats::load(&from).map_err(|err| format!("{}: {}", from.display(), err))?;
+    let mut changes = vec![];
+    let mut unfixable = vec![];
+    let mut derived = vec![];
+    for (loc, index, _) in map.cells() {
+        // fixing one cell may have moved the cells derived from it
+        let Some(color) = map.color_at(loc) else { continue };
+        if color.contrast_ratio(background) >= target {
+            continue;
+        }
+        if let Some(link) = map.link_at(loc) {
+            derived.push((map.label_of(index), map.label_of(link.source)));
+            continue;
+        }
+        match fix_contrast(color, background, target) {
+            Some(fixed) => {
+                map.recolor(index, fixed);
+                changes.push((map.label_of(index), color.to_hex(), fixed.to_hex()));
+            }
+            None => unfixable.push(map.label_of(index)),
+        }
+    }
+    formats::save(&map, &to).map_err(|err| format!("{}: {}", to.display(), err))?;
+
+    Ok(match output {
+        Output::Text => changes.iter()
+            .map(|(label, old, new)| format!("{}\t{} -> {}", label, old, new))
+            .chain(unfixable.iter().map(|label| format!("{}\tcannot reach the target", label)))
+            .chain(derived.iter().map(|(label, source)| format!("{}\tderived from {}, left as is", label, source)))
+            .collect::<Vec<String>>()
+            .join("\n"),
+        Output::Json => json!({
+            "changes": changes.iter()
+                .map(|(label, old, new)| json!({ "cell": label, "from": old, "to": new }))
+                .collect::<Vec<_>>(),
+            "unfixable": unfixable,
+            "derived": derived.iter()
+                .map(|(label, source)| json!({ "cell": label, "source": source }))
+                .collect::<Vec<_>>(),
+        }).to_string(),
+    })
+}
+
+fn run() -> Result<String, String> {
+    let args = Args::parse(env::args().skip(1))?;
+    let output = match args.option("format").unwrap_or("text") {
+        "text" => Output::Text,
+        "json" => Output::Json,
+        format => return Err(format!("unknown output format: {}", format)),
+    };
+
+    match args.positional.first().map(String::as_str) {
+        Some("name") => name(&args, output),
+        Some("build-db") => write_db(&args, output),
+        Some("convert") => convert(&args, output),
+        Some("shades") => shades_of(&args, output),
+        Some("contrast") => contrast(&args, output),
+        Some("fix-contrast") => fix_contrast_of(&args, output),
+        Some(command) => Err(format!("unknown command: {}\n{}", command, USAGE)),
+        None => Err(USAGE.to_owned()),
+    }
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(out) => {
+            println!("{}", out);
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod cli_tests {
+    use super::{Args, Output, shades_of, contrast, target_ratio};
+
+    fn args(line: &str) -> Args {
+        Args::parse(line.split_whitespace().map(str::to_owned)).unwrap()
+    }
+
+    #[test]
+    fn parse_args() {
+        let parsed = args("name --db a.json #ff0000 --format json --db b.csv");
+        assert_eq!(parsed.positional, ["name", "#ff0000"]);
+        assert_eq!(parsed.option("format"), Some("json"));
+        assert_eq!(parsed.options("db"), ["a.json", "b.csv"]);
+        // the last one wins
+        assert_eq!(parsed.option("db"), Some("b.csv"));
+        assert_eq!(parsed.option("count"), None);
+        assert_eq!(parsed.positional(1, "color"), Ok("#ff0000"));
+        assert_eq!(parsed.positional(2, "color"), Err("missing color".to_owned()));
+
+        let missing = Args::parse(["shades", "#ff0000", "--count"].map(str::to_owned).into_iter());
+        assert_eq!(missing.err(), Some("missing value for --count".to_owned()));
+    }
+
+    #[test]
+    fn parse_options() {
+        assert_eq!(target_ratio(&args("contrast")), Ok(4.5));
+        assert_eq!(target_ratio(&args("contrast --target aaa-large")), Ok(4.5));
+        assert_eq!(target_ratio(&args("contrast --target 7")), Ok(7.0));
+        assert!(target_ratio(&args("contrast --target 30")).is_err());
+
+        let shades = shades_of(&args("shades #808080 --count 3 --space oklch"), Output::Text).unwrap();
+        assert_eq!(shades.lines().count(), 3);
+        assert!(shades_of(&args("shades #808080 --space lab"), Output::Text).is_err());
+        assert!(shades_of(&args("shades #808080 --axis alpha"), Output::Text).is_err());
+        assert!(shades_of(&args("shades #808080 --count 0"), Output::Text).is_err());
+
+        let out = contrast(&args("contrast #000000 #ffffff"), Output::Json).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert!((json["ratio"].as_f64().unwrap() - 21.0).abs() < 1e-3);
+        assert_eq!(json["levels"]["AAA"], true);
+    }
+}
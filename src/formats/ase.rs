@@ -0,0 +1,305 @@
+/* 
+ * Adobe Swatch Exchange (`.ase`) palettes. Every group becomes a row of the grid, and runs of
+ * colors outside of any group get a row of their own. All numbers are big-endian:
+ *
+ *      "ASEF" u16:major u16:minor u32:block-count
+ *      block:  u16:type u32:length data
+ *      group start (0xc001): name
+ *      group end   (0xc002):
+ *      color       (0x0001): name [u8; 4]:model f32*:values u16:type
+ *      name:   u16:length (utf-16 units, with the terminating 0) utf-16be
+ *
+ * LAB colors are taken straight into LCH, so they are never clipped to sRGB on import. Colors
+ * are exported as RGB.
+ * */
+
+use std::{fmt, fs, io, path::Path, error::Error, f32::consts::PI};
+
+use crate::{state::MapData, util::color::Color};
+
+pub const EXTENSION: &str = "ase";
+
+const SIGNATURE: &[u8; 4] = b"ASEF";
+const GROUP_START: u16 = 0xc001;
+const GROUP_END: u16 = 0xc002;
+const COLOR_ENTRY: u16 = 0x0001;
+const NORMAL_COLOR: u16 = 2;
+
+#[derive(Debug)]
+pub enum AseError {
+    Io(io::Error),
+    BadSignature,
+    UnsupportedVersion(u16),
+    Truncated,
+    UnknownBlock(u16),
+    UnknownModel([u8; 4]),
+    InvalidName,
+}
+
+impl fmt::Display for AseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AseError::Io(err) => write!(f, "{}", err),
+            AseError::BadSignature => write!(f, "not an ASE file"),
+            AseError::UnsupportedVersion(v) => write!(f, "unsupported ASE version {}", v),
+            AseError::Truncated => write!(f, "unexpected end of file"),
+            AseError::UnknownBlock(t) => write!(f, "unknown block type {:#06x}", t),
+            AseError::UnknownModel(m) => write!(f, "unknown color model {:?}", String::from_utf8_lossy(m)),
+            AseError::InvalidName => write!(f, "invalid utf-16 name"),
+        }
+    }
+}
+
+impl Error for AseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AseError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for AseError {
+    fn from(err: io::Error) -> Self {
+        AseError::Io(err)
+    }
+}
+
+impl From<AseError> for io::Error {
+    fn from(err: AseError) -> Self {
+        match err {
+            AseError::Io(err) => err,
+            err => io::Error::new(io::ErrorKind::InvalidData, err),
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], AseError> {
+        if self.data.len() < n {
+            return Err(AseError::Truncated);
+        }
+        let (head, tail) = self.data.split_at(n);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn u16(&mut self) -> Result<u16, AseError> {
+        Ok(u16::from_be_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, AseError> {
+        Ok(u32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> Result<f32, AseError> {
+        Ok(f32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn name(&mut self) -> Result<String, AseError> {
+        let len = self.u16()? as usize;
+        let units: Vec<u16> = self.bytes(len * 2)?
+            .chunks(2)
+            .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+            .take_while(|unit| *unit != 0)
+            .collect();
+        String::from_utf16(&units).map_err(|_| AseError::InvalidName)
+    }
+}
+
+fn rgb(r: f32, g: f32, b: f32) -> Color {
+    let channel = |x: f32| (x.clamp(0.0, 1.0) * 255.0).round() as u8;
+    Color::from_hex(&format!("#{:02x}{:02x}{:02x}", channel(r), channel(g), channel(b)))
+}
+
+fn color(reader: &mut Reader) -> Result<Color, AseError> {
+    let model: [u8; 4] = reader.bytes(4)?.try_into().unwrap();
+    match &model {
+        b"RGB " => Ok(rgb(reader.f32()?, reader.f32()?, reader.f32()?)),
+        b"LAB " => {
+            let (l, a, b) = (reader.f32()?, reader.f32()?, reader.f32()?);
+            Ok(Color {
+                luminance: l,
+                chroma: a.hypot(b) / 100.0,
+                hue: (b.atan2(a) / (2.0 * PI)).rem_euclid(1.0),
+                alpha: 1.0,
+            })
+        }
+        b"CMYK" => {
+            let (c, m, y, k) = (reader.f32()?, reader.f32()?, reader.f32()?, reader.f32()?);
+            Ok(rgb((1.0 - c) * (1.0 - k), (1.0 - m) * (1.0 - k), (1.0 - y) * (1.0 - k)))
+        }
+        b"Gray" => {
+            let g = reader.f32()?;
+            Ok(rgb(g, g, g))
+        }
+        _ => Err(AseError::UnknownModel(model)),
+    }
+}
+
+pub fn from_bytes(data: &[u8]) -> Result<MapData, AseError> {
+    let mut reader = Reader { data };
+
+    if reader.bytes(4).map_err(|_| AseError::BadSignature)? != SIGNATURE {
+        return Err(AseError::BadSignature);
+    }
+    let major = reader.u16()?;
+    if major != 1 {
+        return Err(AseError::UnsupportedVersion(major));
+    }
+    reader.u16()?;
+    let blocks = reader.u32()?;
+
+    let mut rows: Vec<Vec<(Color, Option<String>)>> = vec![];
+    // whether colors are added to the last row, they are while inside a group
+    let mut open_row = false;
+
+    for _ in 0..blocks {
+        let kind = reader.u16()?;
+        let len = reader.u32()? as usize;
+        let mut block = Reader { data: reader.bytes(len)? };
+
+        match kind {
+            GROUP_START => {
+                rows.push(vec![]);
+                open_row = true;
+            }
+            GROUP_END => open_row = false,
+            COLOR_ENTRY => {
+                let name = Some(block.name()?).filter(|name| !name.is_empty());
+                let color = color(&mut block)?;
+                if !open_row {
+                    rows.push(vec![]);
+                    open_row = true;
+                }
+                rows.last_mut().unwrap().push((color, name));
+            }
+            _ => return Err(AseError::UnknownBlock(kind)),
+        }
+    }
+
+    rows.retain(|row| !row.is_empty());
+    let size = (rows.len().max(1), rows.iter().map(Vec::len).max().unwrap_or(1));
+    let cells = rows.into_iter()
+        .enumerate()
+        .flat_map(|(r, row)| row.into_iter().enumerate().map(move |(c, (color, name))| ((r, c), color, name)))
+        .collect();
+
+    Ok(MapData::from_cells(size.0, size.1, cells))
+}
+
+fn write_name(out: &mut Vec<u8>, name: &str) {
+    let units: Vec<u16> = name.encode_utf16().chain([0]).collect();
+    out.extend((units.len() as u16).to_be_bytes());
+    for unit in units {
+        out.extend(unit.to_be_bytes());
+    }
+}
+
+fn write_block(out: &mut Vec<u8>, kind: u16, data: Vec<u8>) {
+    out.extend(kind.to_be_bytes());
+    out.extend((data.len() as u32).to_be_bytes());
+    out.extend(data);
+}
+
+pub fn to_bytes(map: &MapData) -> Vec<u8> {
+    let (rows, _) = map.size();
+    let cells = map.cells();
+
+    let mut blocks = vec![];
+    let mut count = 0u32;
+
+    for r in 0..rows {
+        let row: Vec<_> = cells.iter().filter(|((row, _), _, _)| *row == r).collect();
+        if row.is_empty() {
+            continue;
+        }
+
+        let mut group = vec![];
+        write_name(&mut group, &format!("Row {}", r + 1));
+        write_block(&mut blocks, GROUP_START, group);
+        count += row.len() as u32 + 2;
+
+        for (_, index, color) in row {
+            // swatches have no opacity
+            let [r, g, b] = color.to_rgb();
+            let channel = |x: u8| x as f32 / 255.0;
+
+            let mut entry = vec![];
+            write_name(&mut entry, map.name_of(*index).unwrap_or_default());
+            entry.extend(b"RGB ");
+            for value in [channel(r), channel(g), channel(b)] {
+                entry.extend(value.to_be_bytes());
+            }
+            entry.extend(NORMAL_COLOR.to_be_bytes());
+            write_block(&mut blocks, COLOR_ENTRY, entry);
+        }
+
+        write_block(&mut blocks, GROUP_END, vec![]);
+    }
+
+    let mut out = SIGNATURE.to_vec();
+    out.extend(1u16.to_be_bytes());
+    out.extend(0u16.to_be_bytes());
+    out.extend(count.to_be_bytes());
+    out.extend(blocks);
+    out
+}
+
+pub fn load(path: &Path) -> Result<MapData, AseError> {
+    from_bytes(&fs::read(path)?)
+}
+
+pub fn save(map: &MapData, path: &Path) -> Result<(), AseError> {
+    Ok(fs::write(path, to_bytes(map))?)
+}
+
+#[cfg(test)]
+mod ase_tests {
+    use super::{from_bytes, to_bytes, write_block, write_name, AseError, COLOR_ENTRY, NORMAL_COLOR};
+    use crate::{state::MapData, util::color::Color};
+
+    #[test]
+    fn round_trip() {
+        let map = MapData::default();
+        let again = from_bytes(&to_bytes(&map)).unwrap();
+
+        // the second row of the default palette starts at column 0
+        assert_eq!(again.size(), (2, 2));
+        assert_eq!(again.color_at((0, 0)), map.color_at((0, 0)));
+        assert_eq!(again.color_at((1, 1)), map.color_at((1, 1)));
+        assert_eq!(again.name_of(1), None);
+    }
+
+    #[test]
+    fn lab_is_not_clipped() {
+        let mut entry = vec![];
+        write_name(&mut entry, "Vivid");
+        entry.extend(b"LAB ");
+        for value in [0.5f32, 0.0, 120.0] {
+            entry.extend(value.to_be_bytes());
+        }
+        entry.extend(NORMAL_COLOR.to_be_bytes());
+
+        let mut data = b"ASEF\0\x01\0\0\0\0\0\x01".to_vec();
+        write_block(&mut data, COLOR_ENTRY, entry);
+
+        let map = from_bytes(&data).unwrap();
+        let Color { luminance, chroma, hue, .. } = map.color_at((0, 0)).unwrap();
+        assert_eq!(luminance, 0.5);
+        assert!((chroma - 1.2).abs() < 1e-6);
+        assert!((hue - 0.25).abs() < 1e-6);
+        assert_eq!(map.name_of(0), Some("Vivid"));
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(from_bytes(b"GIF8"), Err(AseError::BadSignature)));
+        assert!(matches!(from_bytes(b"ASEF\0\x02\0\0\0\0\0\0"), Err(AseError::UnsupportedVersion(2))));
+        assert!(matches!(from_bytes(b"ASEF\0\x01\0\0\0\0\0\x01\0\x01"), Err(AseError::Truncated)));
+    }
+}
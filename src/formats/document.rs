@@ -0,0 +1,173 @@
+/* 
+ * The `.blush.json` palette document. Every document carries a schema `version` next to the
+ * serialized `MapData`; documents written by older versions are upgraded by `migrate` before
+ * they are deserialized, so format changes only need a new migration step.
+ * */
+
+use std::{fs, io, path::Path};
+
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+
+use crate::state::MapData;
+
+pub const EXTENSION: &str = "blush.json";
+pub const VERSION: u64 = 3;
+
+#[derive(Serialize)]
+struct DocumentRef<'a> {
+    version: u64,
+    palette: &'a MapData,
+}
+
+#[derive(Deserialize)]
+struct Document {
+    palette: MapData,
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// Bring a document of any older version up to `VERSION`.
+fn migrate(doc: Value) -> Result<Value, io::Error> {
+    let version = doc.get("version")
+        .and_then(Value::as_u64)
+        .ok_or_else(|| invalid("missing schema version".to_owned()))?;
+
+    if version > VERSION {
+        return Err(invalid(format!("document version {} is newer than the supported version {}", 
+                                   version, VERSION)));
+    }
+
+    let mut doc = doc;
+    for from in version..VERSION {
+        doc = match from {
+            1 => v1_to_v2(doc),
+            2 => v2_to_v3(doc),
+            _ => return Err(invalid(format!("unknown document version {}", from))),
+        };
+    }
+    Ok(doc)
+}
+
+// Version 2 added relations between cells.
+fn v1_to_v2(mut doc: Value) -> Value {
+    if let Some(palette) = doc.get_mut("palette").and_then(Value::as_object_mut) {
+        palette.insert("relations".to_owned(), Value::Object(Default::default()));
+    }
+    doc
+}
+
+// Version 3 added user-assigned cell names.
+fn v2_to_v3(mut doc: Value) -> Value {
+    if let Some(palette) = doc.get_mut("palette").and_then(Value::as_object_mut) {
+        palette.insert("names".to_owned(), Value::Object(Default::default()));
+    }
+    doc
+}
+
+pub fn to_string(map: &MapData) -> Result<String, io::Error> {
+    let doc = DocumentRef { version: VERSION, palette: map };
+    Ok(serde_json::to_string_pretty(&doc)?)
+}
+
+pub fn from_str(data: &str) -> Result<MapData, io::Error> {
+    let doc: Value = serde_json::from_str(data)?;
+    let doc: Document = serde_json::from_value(migrate(doc)?)?;
+    doc.palette.loaded().map_err(invalid)
+}
+
+pub fn save(map: &MapData, path: &Path) -> Result<(), io::Error> {
+    fs::write(path, to_string(map)?)
+}
+
+pub fn load(path: &Path) -> Result<MapData, io::Error> {
+    from_str(&fs::read_to_string(path)?)
+}
+
+#[cfg(test)]
+mod document_tests {
+    use std::io;
+
+    use super::{to_string, from_str, VERSION};
+    use crate::{state::MapData, gen::relations::Relation};
+
+    #[test]
+    fn round_trip() {
+        let map = MapData::default();
+        let loaded = from_str(&to_string(&map).unwrap()).unwrap();
+
+        assert_eq!(loaded.size(), map.size());
+        for r in 0..map.size().0 {
+            for c in 0..map.size().1 {
+                assert_eq!(loaded.index_at((r, c)), map.index_at((r, c)));
+                assert_eq!(loaded.color_at((r, c)), map.color_at((r, c)));
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_newer_version() {
+        let version = format!("\"version\": {}", VERSION);
+        let doc = to_string(&MapData::default()).unwrap().replace(&version, "\"version\": 99");
+        assert!(from_str(&doc).is_err());
+    }
+
+    #[test]
+    fn migrates_v1() {
+        let doc = r#"{
+            "version": 1,
+            "palette": {
+                "rows": 1, "cols": 2,
+                "map": [[[0, 0], 0]],
+                "colors": [[0, {"luminance": 0.5, "chroma": 0.2, "hue": 0.1}]],
+                "next_color": 1
+            }
+        }"#;
+        let map = from_str(doc).unwrap();
+        assert_eq!(map.size(), (1, 2));
+        assert_eq!(map.color_at((0, 0)).unwrap().luminance, 0.5);
+        assert!(map.link_at((0, 0)).is_none());
+    }
+
+    // The next color must not take the index of a color already in the document.
+    #[test]
+    fn loaded_palette_keeps_indices() {
+        let doc = r#"{
+            "version": 1,
+            "palette": {
+                "rows": 1, "cols": 2,
+                "map": [[[0, 0], 4]],
+                "colors": [[4, {"luminance": 0.5, "chroma": 0.2, "hue": 0.1}]],
+                "next_color": 2
+            }
+        }"#;
+        let map = from_str(doc).unwrap();
+        assert_eq!(map.color_at((0, 0)).unwrap().luminance, 0.5);
+        let saved: serde_json::Value = serde_json::from_str(&to_string(&map).unwrap()).unwrap();
+        assert_eq!(saved["palette"]["next_color"], 5);
+    }
+
+    #[test]
+    fn rejects_cyclic_relations() {
+        let doc = r#"{
+            "version": 3,
+            "palette": {
+                "rows": 1, "cols": 2,
+                "map": [[[0, 0], 0], [[0, 1], 1]],
+                "colors": [[0, {"luminance": 0.5, "chroma": 0.2, "hue": 0.1}],
+                           [1, {"luminance": 0.5, "chroma": 0.2, "hue": 0.6}]],
+                "relations": {"0": {"source": 1, "relation": RELATION},
+                              "1": {"source": 0, "relation": RELATION}},
+                "names": {},
+                "next_color": 2
+            }
+        }"#;
+        let relation = serde_json::to_string(&Relation::complement()).unwrap();
+        let err = from_str(&doc.replace("RELATION", &relation)).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let dangling = doc.replace("RELATION", &relation).replace("\"source\": 1", "\"source\": 7");
+        assert!(from_str(&dangling).is_err());
+    }
+}
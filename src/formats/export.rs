@@ -0,0 +1,158 @@
+/* 
+ * Export a palette as stylesheet variables. Cells are written in row-major order, named after
+ * their user-assigned name (turned into an identifier) or their index.
+ * */
+
+use std::collections::HashSet;
+
+use crate::state::{MapData, to_repr};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stylesheet {
+    Css,
+    Scss,
+    Tailwind,
+}
+
+impl Stylesheet {
+    pub const ALL: [Stylesheet; 3] = [Stylesheet::Css, Stylesheet::Scss, Stylesheet::Tailwind];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stylesheet::Css => "CSS",
+            Stylesheet::Scss => "SCSS",
+            Stylesheet::Tailwind => "Tailwind",
+        }
+    }
+
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Stylesheet::Css => "palette.css",
+            Stylesheet::Scss => "_palette.scss",
+            Stylesheet::Tailwind => "tailwind.config.js",
+        }
+    }
+
+    pub fn export(self, map: &MapData) -> String {
+        match self {
+            Stylesheet::Css => css(map),
+            Stylesheet::Scss => scss(map),
+            Stylesheet::Tailwind => tailwind(map),
+        }
+    }
+}
+
+// Lowercase, with every run of other characters replaced by a single dash. Identifiers can't
+// start with a digit, those get a `color-` prefix.
+fn identifier(name: &str) -> String {
+    let mut ident = String::new();
+    for ch in name.chars() {
+        if ch.is_ascii_alphanumeric() {
+            ident.push(ch.to_ascii_lowercase());
+        } else if !ident.is_empty() && !ident.ends_with('-') {
+            ident.push('-');
+        }
+    }
+    let ident = ident.trim_end_matches('-');
+    if ident.starts_with(|ch: char| ch.is_ascii_digit()) {
+        format!("color-{}", ident)
+    } else {
+        ident.to_owned()
+    }
+}
+
+// (identifier, hex) for every cell, identifiers are unique.
+fn entries(map: &MapData) -> Vec<(String, String)> {
+    let mut seen = HashSet::new();
+    map.cells()
+        .into_iter()
+        .map(|(_, index, color)| {
+            let base = match map.name_of(index).map(identifier) {
+                Some(ident) if !ident.is_empty() => ident,
+                _ => format!("color-{}", to_repr(index)),
+            };
+            // repeated names get the cell index, then a count if another name already ends with it
+            let mut ident = base.clone();
+            let mut copies = 0;
+            while !seen.insert(ident.clone()) {
+                copies += 1;
+                ident = match copies {
+                    1 => format!("{}-{}", base, to_repr(index)),
+                    _ => format!("{}-{}-{}", base, to_repr(index), copies),
+                };
+            }
+            (ident, color.to_hex())
+        })
+        .collect()
+}
+
+pub fn css(map: &MapData) -> String {
+    let mut out = String::from(":root {\n");
+    for (ident, hex) in entries(map) {
+        out += &format!("  --{}: {};\n", ident, hex);
+    }
+    out += "}\n";
+    out
+}
+
+pub fn scss(map: &MapData) -> String {
+    entries(map)
+        .into_iter()
+        .map(|(ident, hex)| format!("${}: {};\n", ident, hex))
+        .collect()
+}
+
+pub fn tailwind(map: &MapData) -> String {
+    let mut out = String::from("module.exports = {\n  theme: {\n    extend: {\n      colors: {\n");
+    for (ident, hex) in entries(map) {
+        out += &format!("        '{}': '{}',\n", ident, hex);
+    }
+    out += "      },\n    },\n  },\n};\n";
+    out
+}
+
+#[cfg(test)]
+mod export_tests {
+    use super::{css, scss, tailwind, identifier};
+    use crate::{state::MapData, util::color::Color, formats::document};
+
+    #[test]
+    fn identifiers() {
+        assert_eq!(identifier("Alice Blue"), "alice-blue");
+        assert_eq!(identifier("  brand / primary!"), "brand-primary");
+        assert_eq!(identifier("1 red"), "color-1-red");
+    }
+
+    #[test]
+    fn default_palette() {
+        let map = MapData::default();
+        assert_eq!(css(&map), ":root {\n  --color-0: #fcfafa;\n  --color-1: #c8d3d5;\n  --color-2: #a4b8c4;\n}\n");
+        assert_eq!(scss(&map), "$color-0: #fcfafa;\n$color-1: #c8d3d5;\n$color-2: #a4b8c4;\n");
+        assert!(tailwind(&map).contains("        'color-2': '#a4b8c4',\n"));
+    }
+
+    #[test]
+    fn colliding_names() {
+        let doc = r#"{
+            "version": 3,
+            "palette": {
+                "rows": 1, "cols": 4,
+                "map": [[[0, 0], 0], [[0, 1], 1], [[0, 2], 2], [[0, 3], 3]],
+                "colors": [[0, RED], [1, RED], [2, RED], [3, RED]],
+                "relations": {},
+                "names": {"0": "red-2", "1": "red", "2": "red", "3": "red-2-2"},
+                "next_color": 4
+            }
+        }"#;
+        let red = serde_json::to_string(&Color::from_hex("#ff0000")).unwrap();
+        let map = document::from_str(&doc.replace("RED", &red)).unwrap();
+        assert_eq!(scss(&map), "$red-2: #ff0000;\n$red: #ff0000;\n$red-2-2: #ff0000;\n$red-2-2-3: #ff0000;\n");
+    }
+
+    #[test]
+    fn translucent_colors() {
+        let scrim = Color::from_hex("#00000080");
+        let map = MapData::from_cells(1, 1, vec![((0, 0), scrim, Some("scrim".to_owned()))]);
+        assert_eq!(css(&map), ":root {\n  --scrim: #00000080;\n}\n");
+    }
+}
@@ -0,0 +1,182 @@
+/* 
+ * GIMP palettes (`.gpl`), as used by GIMP and Inkscape. A palette is a flat list of colors, laid
+ * out in rows of `Columns:` colors; empty cells of the grid are not kept when exporting.
+ *
+ *      GIMP Palette
+ *      Name: blush
+ *      Columns: 10
+ *      #
+ *      252 250 250	Name of the color
+ * */
+
+use std::{fmt, fs, io, path::Path, error::Error};
+
+use crate::{state::MapData, util::color::Color};
+
+pub const EXTENSION: &str = "gpl";
+
+const HEADER: &str = "GIMP Palette";
+const DEFAULT_COLUMNS: usize = 10;
+
+#[derive(Debug)]
+pub enum GplError {
+    Io(io::Error),
+    MissingHeader,
+    InvalidColumns { line: usize },
+    InvalidColor { line: usize },
+}
+
+impl fmt::Display for GplError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GplError::Io(err) => write!(f, "{}", err),
+            GplError::MissingHeader => write!(f, "missing \"{}\" header", HEADER),
+            GplError::InvalidColumns { line } => write!(f, "invalid column count on line {}", line),
+            GplError::InvalidColor { line } => write!(f, "invalid color on line {}", line),
+        }
+    }
+}
+
+impl Error for GplError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            GplError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for GplError {
+    fn from(err: io::Error) -> Self {
+        GplError::Io(err)
+    }
+}
+
+impl From<GplError> for io::Error {
+    fn from(err: GplError) -> Self {
+        match err {
+            GplError::Io(err) => err,
+            err => io::Error::new(io::ErrorKind::InvalidData, err),
+        }
+    }
+}
+
+// "r g b name", with the name being optional.
+fn parse_color(line: &str) -> Option<(Color, Option<String>)> {
+    let mut rest = line.trim_start();
+    let mut rgb = [0u8; 3];
+    for channel in rgb.iter_mut() {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        *channel = rest[..end].parse().ok()?;
+        rest = rest[end..].trim_start();
+    }
+
+    let [r, g, b] = rgb;
+    let color = Color::from_hex(&format!("#{:02x}{:02x}{:02x}", r, g, b));
+    let name = Some(rest.trim_end().to_owned()).filter(|name| !name.is_empty());
+    Some((color, name))
+}
+
+pub fn from_str(data: &str) -> Result<MapData, GplError> {
+    let mut lines = data.lines().enumerate();
+
+    match lines.next() {
+        Some((_, line)) if line.trim() == HEADER => (),
+        _ => return Err(GplError::MissingHeader),
+    }
+
+    let mut cols = DEFAULT_COLUMNS;
+    let mut colors = vec![];
+
+    for (idx, line) in lines {
+        let line_no = idx + 1;
+        let trimmed = line.trim();
+
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with("Name:") {
+            continue;
+        }
+
+        if let Some(value) = trimmed.strip_prefix("Columns:") {
+            cols = value.trim().parse().map_err(|_| GplError::InvalidColumns { line: line_no })?;
+            continue;
+        }
+
+        colors.push(parse_color(line).ok_or(GplError::InvalidColor { line: line_no })?);
+    }
+
+    // Columns: 0 means the palette has no preferred layout
+    if cols == 0 {
+        cols = DEFAULT_COLUMNS;
+    }
+    let rows = colors.len().div_ceil(cols).max(1);
+
+    let cells = colors.into_iter()
+        .enumerate()
+        .map(|(i, (color, name))| ((i / cols, i % cols), color, name))
+        .collect();
+
+    Ok(MapData::from_cells(rows, cols, cells))
+}
+
+pub fn to_string(map: &MapData, name: &str) -> String {
+    let (_, cols) = map.size();
+    let mut out = format!("{}\nName: {}\nColumns: {}\n#\n", HEADER, name, cols);
+
+    for (_, index, color) in map.cells() {
+        // GIMP palettes have no opacity
+        let [r, g, b] = color.to_rgb();
+        out += &format!("{:3} {:3} {:3}", r, g, b);
+        if let Some(name) = map.name_of(index) {
+            out += &format!("\t{}", name);
+        }
+        out.push('\n');
+    }
+
+    out
+}
+
+pub fn load(path: &Path) -> Result<MapData, GplError> {
+    from_str(&fs::read_to_string(path)?)
+}
+
+// The palette is named after the file.
+pub fn save(map: &MapData, path: &Path) -> Result<(), GplError> {
+    let name = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("blush");
+    Ok(fs::write(path, to_string(map, name))?)
+}
+
+#[cfg(test)]
+mod gpl_tests {
+    use super::{from_str, to_string, GplError};
+    use crate::util::color::Color;
+
+    const PALETTE: &str = "GIMP Palette\nName: test\nColumns: 2\n# comment\n\
+                           255   0   0\tRed\n  0 255   0\n  0   0 255\tDeep  blue \n";
+
+    #[test]
+    fn parse() {
+        let map = from_str(PALETTE).unwrap();
+        assert_eq!(map.size(), (2, 2));
+        assert_eq!(map.color_at((0, 0)), Some(Color::from_hex("#ff0000")));
+        assert_eq!(map.color_at((1, 0)), Some(Color::from_hex("#0000ff")));
+        assert!(map.color_at((1, 1)).is_none());
+
+        let names: Vec<Option<&str>> = map.cells().iter().map(|(_, index, _)| map.name_of(*index)).collect();
+        assert_eq!(names, [Some("Red"), None, Some("Deep  blue")]);
+    }
+
+    #[test]
+    fn round_trip() {
+        let map = from_str(PALETTE).unwrap();
+        let again = from_str(&to_string(&map, "test")).unwrap();
+        assert_eq!(map.cells(), again.cells());
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(from_str("Not a palette\n"), Err(GplError::MissingHeader)));
+        assert!(matches!(from_str("GIMP Palette\nColumns: x\n"), Err(GplError::InvalidColumns { line: 2 })));
+        assert!(matches!(from_str("GIMP Palette\n1 2\n"), Err(GplError::InvalidColor { line: 2 })));
+        assert!(matches!(from_str("GIMP Palette\n1 2 300\n"), Err(GplError::InvalidColor { line: 2 })));
+    }
+}
@@ -0,0 +1,183 @@
+/* 
+ * Extract a palette from a picture. Pixels are first binned into a coarse RGB histogram, and
+ * the bins are then clustered with a weighted k-means using the perceptual `MetricPoint`
+ * distance of `Color`. Centroids are averaged in Lab, where averaging hues is well behaved.
+ * The resulting colors are ordered by the share of the picture they cover.
+ * */
+
+use std::{collections::BTreeMap, io, path::Path};
+
+use rand::{Rng, SeedableRng};
+
+use crate::{state::MapData, util::{color::Color, vptree::MetricPoint, metric::{lab, from_lab}}};
+
+pub const EXTENSIONS: [&str; 3] = ["png", "jpg", "jpeg"];
+pub const DEFAULT_COUNT: usize = 8;
+
+const BIN_SHIFT: u32 = 3;
+const MAX_ITERATIONS: usize = 20;
+const MAX_COLUMNS: usize = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Swatch {
+    pub color: Color,
+    // fraction of the pixels closest to this color
+    pub share: f32,
+}
+
+// Mean color of every occupied histogram bin, weighted by its pixel count.
+fn histogram(pixels: &[[u8; 3]]) -> Vec<(Color, f32)> {
+    let mut bins: BTreeMap<[u8; 3], ([u64; 3], u64)> = BTreeMap::new();
+    for pixel in pixels {
+        let (sum, count) = bins.entry(pixel.map(|x| x >> BIN_SHIFT)).or_default();
+        for (s, x) in sum.iter_mut().zip(pixel) {
+            *s += *x as u64;
+        }
+        *count += 1;
+    }
+
+    bins.into_values()
+        .map(|(sum, count)| {
+            let [r, g, b] = sum.map(|s| (s / count) as u8);
+            (Color::from_rgb(r, g, b), count as f32)
+        })
+        .collect()
+}
+
+fn nearest(centroids: &[Color], color: &Color) -> usize {
+    let dists = centroids.iter().map(|c| MetricPoint::dist(c, color));
+    dists.enumerate()
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(i, _)| i)
+        .unwrap()
+}
+
+// k-means++ seeding, starting from the most common color.
+fn seed(points: &[(Color, f32)], k: usize) -> Vec<Color> {
+    let mut rng = rand::rngs::SmallRng::seed_from_u64(0xfaceu64);
+    let first = points.iter().max_by(|a, b| a.1.total_cmp(&b.1)).unwrap().0;
+    let mut centroids = vec![first];
+
+    while centroids.len() < k {
+        let weights: Vec<f32> = points.iter()
+            .map(|(color, weight)| {
+                let d = MetricPoint::dist(&centroids[nearest(&centroids, color)], color);
+                weight * d * d
+            })
+            .collect();
+        let total: f32 = weights.iter().sum();
+        if total <= 0.0 {
+            break;
+        }
+
+        let mut target = rng.gen_range(0.0..total);
+        let mut chosen = points.len() - 1;
+        for (i, w) in weights.iter().enumerate() {
+            if target < *w {
+                chosen = i;
+                break;
+            }
+            target -= w;
+        }
+        centroids.push(points[chosen].0);
+    }
+
+    centroids
+}
+
+// Up to `count` dominant colors of the pixels, most common first.
+pub fn dominant_colors(pixels: &[[u8; 3]], count: usize) -> Vec<Swatch> {
+    let points = histogram(pixels);
+    if points.is_empty() || count == 0 {
+        return vec![];
+    }
+
+    let mut centroids = seed(&points, count.min(points.len()));
+    let mut assignment = vec![usize::MAX; points.len()];
+
+    for _ in 0..MAX_ITERATIONS {
+        let next: Vec<usize> = points.iter().map(|(color, _)| nearest(&centroids, color)).collect();
+        if next == assignment {
+            break;
+        }
+        assignment = next;
+
+        let mut sums = vec![([0.0f64; 3], 0.0f64); centroids.len()];
+        for ((color, weight), cluster) in points.iter().zip(&assignment) {
+            let (sum, total) = &mut sums[*cluster];
+            for (s, x) in sum.iter_mut().zip(lab(color)) {
+                *s += x * *weight as f64;
+            }
+            *total += *weight as f64;
+        }
+        for (centroid, (sum, total)) in centroids.iter_mut().zip(sums) {
+            if total > 0.0 {
+                *centroid = from_lab(sum.map(|s| s / total));
+            }
+        }
+    }
+
+    let mut weights = vec![0.0f32; centroids.len()];
+    for ((_, weight), cluster) in points.iter().zip(&assignment) {
+        weights[*cluster] += weight;
+    }
+    let total: f32 = weights.iter().sum();
+
+    let mut swatches: Vec<Swatch> = centroids.into_iter()
+        .zip(weights)
+        .filter(|(_, weight)| *weight > 0.0)
+        .map(|(color, weight)| Swatch { color, share: weight / total })
+        .collect();
+    swatches.sort_by(|a, b| b.share.total_cmp(&a.share));
+    swatches
+}
+
+pub fn load_pixels(path: &Path) -> Result<Vec<[u8; 3]>, io::Error> {
+    let img = ::image::open(path)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    Ok(img.to_rgb8().pixels().map(|p| p.0).collect())
+}
+
+// Lay the swatches out in rows, leaving them unnamed.
+pub fn to_palette(swatches: &[Swatch]) -> MapData {
+    let cols = swatches.len().clamp(1, MAX_COLUMNS);
+    let rows = swatches.len().div_ceil(cols).max(1);
+    let cells = swatches.iter()
+        .enumerate()
+        .map(|(i, swatch)| ((i / cols, i % cols), swatch.color, None))
+        .collect();
+
+    MapData::from_cells(rows, cols, cells)
+}
+
+pub fn load(path: &Path, count: usize) -> Result<MapData, io::Error> {
+    let pixels = load_pixels(path)?;
+    Ok(to_palette(&dominant_colors(&pixels, count)))
+}
+
+#[cfg(test)]
+mod image_tests {
+    use super::dominant_colors;
+    use crate::util::color::Color;
+
+    #[test]
+    fn ordered_by_prevalence() {
+        let mut pixels = vec![[200, 30, 30]; 600];
+        pixels.extend(vec![[20, 40, 220]; 300]);
+        pixels.extend(vec![[250, 250, 250]; 100]);
+
+        let swatches = dominant_colors(&pixels, 3);
+        assert_eq!(swatches.len(), 3);
+        assert_eq!(swatches[0].color.to_hex(), Color::from_rgb(200, 30, 30).to_hex());
+        assert_eq!(swatches[1].color.to_hex(), Color::from_rgb(20, 40, 220).to_hex());
+        assert!((swatches[0].share - 0.6).abs() < 1e-6);
+        assert!((swatches[2].share - 0.1).abs() < 1e-6);
+    }
+
+    #[test]
+    fn fewer_colors_than_asked() {
+        let pixels = vec![[10, 10, 10]; 10];
+        assert_eq!(dominant_colors(&pixels, 5).len(), 1);
+        assert!(dominant_colors(&[], 5).is_empty());
+    }
+}
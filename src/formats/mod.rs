@@ -0,0 +1,78 @@
+/* 
+ * Palette file formats. `load` and `save` pick the format from the file extension, and report
+ * format specific errors as `io::Error`s with the typed error as their source.
+ * */
+
+use std::{fs, io, path::Path};
+
+use crate::state::MapData;
+
+use export::Stylesheet;
+
+pub mod document;
+pub mod export;
+pub mod gpl;
+pub mod ase;
+pub mod image;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Document,
+    Gpl,
+    Ase,
+    // pictures can only be loaded, as a palette of their dominant colors
+    Image,
+    // and stylesheets can only be saved
+    Stylesheet(Stylesheet),
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Option<Format> {
+        let name = path.file_name()?.to_str()?.to_lowercase();
+        if name.ends_with(&format!(".{}", document::EXTENSION)) {
+            Some(Format::Document)
+        } else if name.ends_with(&format!(".{}", gpl::EXTENSION)) {
+            Some(Format::Gpl)
+        } else if name.ends_with(&format!(".{}", ase::EXTENSION)) {
+            Some(Format::Ase)
+        } else if image::EXTENSIONS.iter().any(|ext| name.ends_with(&format!(".{}", ext))) {
+            Some(Format::Image)
+        } else if name.ends_with(".scss") {
+            Some(Format::Stylesheet(Stylesheet::Scss))
+        } else if name.ends_with(".css") {
+            Some(Format::Stylesheet(Stylesheet::Css))
+        } else if name.ends_with(".js") {
+            Some(Format::Stylesheet(Stylesheet::Tailwind))
+        } else {
+            None
+        }
+    }
+}
+
+fn unknown_format(path: &Path) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, format!("unknown palette format: {}", path.display()))
+}
+
+pub fn load(path: &Path) -> Result<MapData, io::Error> {
+    match Format::from_path(path) {
+        Some(Format::Document) => document::load(path),
+        Some(Format::Gpl) => Ok(gpl::load(path)?),
+        Some(Format::Ase) => Ok(ase::load(path)?),
+        Some(Format::Image) => image::load(path, image::DEFAULT_COUNT),
+        Some(Format::Stylesheet(_)) => 
+            Err(io::Error::new(io::ErrorKind::Unsupported, "palettes can't be loaded from stylesheets")),
+        None => Err(unknown_format(path)),
+    }
+}
+
+pub fn save(map: &MapData, path: &Path) -> Result<(), io::Error> {
+    match Format::from_path(path) {
+        Some(Format::Document) => document::save(map, path),
+        Some(Format::Gpl) => Ok(gpl::save(map, path)?),
+        Some(Format::Ase) => Ok(ase::save(map, path)?),
+        Some(Format::Image) => 
+            Err(io::Error::new(io::ErrorKind::Unsupported, "palettes can't be saved as pictures")),
+        Some(Format::Stylesheet(sheet)) => fs::write(path, sheet.export(map)),
+        None => Err(unknown_format(path)),
+    }
+}
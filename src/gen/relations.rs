@@ -0,0 +1,260 @@
+/* 
+ * Color harmonies around a base color. Hue offsets are taken on the LCH hue circle through
+ * `Color::rotate`, so every member keeps the luminance and chroma of the base color, except for
+ * monochromatic harmonies which step through luminance instead.
+ *
+ * Harmonies are also the building block for relations: a cell can be derived from another cell,
+ * and the `RelationGraph` keeps track of which cells have to be recomputed when a source changes.
+ * Every cell has at most one source, so the graph is a forest and a cycle can only appear when
+ * a cell is linked to one of its own dependents.
+ * */
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use serde::{Serialize, Deserialize};
+
+use crate::util::color::Color;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Harmony {
+    Complementary,
+    SplitComplementary,
+    Triadic,
+    Tetradic,
+    Analogous,
+    Monochromatic,
+}
+
+impl Harmony {
+    pub const ALL: [Harmony; 6] = [Harmony::Complementary, Harmony::SplitComplementary, 
+                                   Harmony::Triadic, Harmony::Tetradic, 
+                                   Harmony::Analogous, Harmony::Monochromatic];
+
+    const MONOCHROMATIC_COUNT: usize = 5;
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Harmony::Complementary => "Complementary",
+            Harmony::SplitComplementary => "Split complementary",
+            Harmony::Triadic => "Triadic",
+            Harmony::Tetradic => "Tetradic",
+            Harmony::Analogous => "Analogous",
+            Harmony::Monochromatic => "Monochromatic",
+        }
+    }
+
+    // Hue offsets of the members other than the base color, as fractions of a full turn.
+    fn hue_offsets(self) -> &'static [f32] {
+        match self {
+            Harmony::Complementary => &[1.0/2.0],
+            Harmony::SplitComplementary => &[5.0/12.0, 7.0/12.0],
+            Harmony::Triadic => &[1.0/3.0, 2.0/3.0],
+            Harmony::Tetradic => &[1.0/6.0, 1.0/2.0, 2.0/3.0],
+            Harmony::Analogous => &[-1.0/12.0, 1.0/12.0],
+            Harmony::Monochromatic => &[],
+        }
+    }
+
+    // Number of members, including the base color.
+    pub fn size(self) -> usize {
+        match self {
+            Harmony::Monochromatic => Harmony::MONOCHROMATIC_COUNT,
+            _ => self.hue_offsets().len() + 1,
+        }
+    }
+
+    // Members of the harmony, starting with `base` itself.
+    pub fn colors(self, base: Color) -> Vec<Color> {
+        let mut colors = vec![base];
+
+        if self == Harmony::Monochromatic {
+            let step = 1.0 / Harmony::MONOCHROMATIC_COUNT as f32;
+            colors.extend((1..Harmony::MONOCHROMATIC_COUNT)
+                .map(|i| Color{luminance: (base.luminance + step * i as f32) % 1.0, ..base}));
+        } else {
+            colors.extend(self.hue_offsets().iter().map(|offset| base.rotate(*offset)));
+        }
+
+        colors
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Relation {
+    // Shift the components of the source, luminance and chroma are clamped, hue wraps around.
+    Offset { luminance: f32, chroma: f32, hue: f32 },
+    // The `member`-th color of a harmony around the source, 0 being the source itself.
+    Harmony { harmony: Harmony, member: usize },
+}
+
+impl Relation {
+    pub fn complement() -> Self {
+        Relation::Harmony { harmony: Harmony::Complementary, member: 1 }
+    }
+
+    pub fn derive(self, source: Color) -> Color {
+        match self {
+            Relation::Offset { luminance, chroma, hue } => Color {
+                luminance: (source.luminance + luminance).clamp(0.0, 1.0),
+                chroma: (source.chroma + chroma).clamp(0.0, 1.0),
+                ..source.rotate(hue)
+            },
+            Relation::Harmony { harmony, member } => 
+                harmony.colors(source).get(member).copied().unwrap_or(source),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Link {
+    pub source: usize,
+    pub relation: Relation,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct RelationGraph {
+    links: BTreeMap<usize, Link>,
+}
+
+impl RelationGraph {
+    pub fn link(&self, target: usize) -> Option<Link> {
+        self.links.get(&target).copied()
+    }
+
+    // Set or clear the link of `target`, returning the previous one.
+    pub fn set_link(&mut self, target: usize, link: Option<Link>) -> Option<Link> {
+        match link {
+            Some(link) => self.links.insert(target, link),
+            None => self.links.remove(&target),
+        }
+    }
+
+    pub fn links(&self) -> &BTreeMap<usize, Link> {
+        &self.links
+    }
+
+    // Deriving `target` from `source` would make `target` depend on itself.
+    pub fn creates_cycle(&self, target: usize, source: usize) -> bool {
+        let mut visited = BTreeSet::new();
+        let mut node = source;
+        loop {
+            if node == target {
+                return true;
+            }
+            // a cycle that does not go through `target`, it is not closed by this link
+            if !visited.insert(node) {
+                return false;
+            }
+            match self.links.get(&node) {
+                Some(link) => node = link.source,
+                None => return false,
+            }
+        }
+    }
+
+    pub fn dependents(&self, source: usize) -> Vec<usize> {
+        self.links.iter()
+            .filter(|(_, link)| link.source == source)
+            .map(|(target, _)| *target)
+            .collect()
+    }
+
+    // Every transitive dependent of `source`, each one after its own source.
+    pub fn propagation_order(&self, source: usize) -> Vec<usize> {
+        let mut order = vec![];
+        let mut visited = BTreeSet::from([source]);
+        let mut queue = VecDeque::from([source]);
+        while let Some(node) = queue.pop_front() {
+            for dep in self.dependents(node) {
+                if visited.insert(dep) {
+                    order.push(dep);
+                    queue.push_back(dep);
+                }
+            }
+        }
+        order
+    }
+
+    // Links read from a file may be anything: check that they join cells for which `exists`
+    // holds, and that no cell depends on itself.
+    pub fn check(&self, exists: impl Fn(usize) -> bool) -> Result<(), String> {
+        for (&target, link) in &self.links {
+            if !exists(target) || !exists(link.source) {
+                return Err(format!("relation from {} to {} refers to a missing color", link.source, target));
+            }
+            if self.creates_cycle(target, link.source) {
+                return Err(format!("color {} is derived from itself", target));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod relations_tests {
+    use super::{Harmony, Relation, RelationGraph, Link};
+    use crate::util::color::Color;
+
+    fn hue_dist(a: f32, b: f32) -> f32 {
+        let d = (a - b).abs();
+        d.min(1.0 - d)
+    }
+
+    #[test]
+    fn harmonies_start_at_base() {
+        let base = Color::default();
+        for harmony in Harmony::ALL {
+            assert_eq!(harmony.colors(base)[0], base);
+        }
+    }
+
+    #[test]
+    fn triadic_is_evenly_spaced() {
+        let base = Color::default();
+        let colors = Harmony::Triadic.colors(base);
+        assert_eq!(colors.len(), 3);
+        for i in 0..3 {
+            let next = colors[(i + 1) % 3];
+            assert!((hue_dist(colors[i].hue, next.hue) - 1.0/3.0).abs() < 1e-5);
+            assert_eq!(colors[i].luminance, base.luminance);
+            assert_eq!(colors[i].chroma, base.chroma);
+        }
+    }
+
+    #[test]
+    fn complement_is_opposite() {
+        let base = Color::default();
+        let complement = Harmony::Complementary.colors(base)[1];
+        assert!((hue_dist(base.hue, complement.hue) - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn offsets_clamp() {
+        let base = Color { luminance: 0.1, ..Color::default() };
+        let darker = Relation::Offset { luminance: -0.2, chroma: 0.0, hue: 0.0 }.derive(base);
+        assert_eq!(darker.luminance, 0.0);
+    }
+
+    #[test]
+    fn cycles_and_order() {
+        let mut graph = RelationGraph::default();
+        let link = |source| Some(Link { source, relation: Relation::complement() });
+        graph.set_link(1, link(0));
+        graph.set_link(2, link(1));
+        graph.set_link(3, link(0));
+
+        assert!(graph.creates_cycle(0, 2));
+        assert!(graph.creates_cycle(1, 1));
+        assert!(!graph.creates_cycle(3, 2));
+        assert_eq!(graph.propagation_order(0), vec![1, 3, 2]);
+        assert!(graph.check(|index| index < 4).is_ok());
+        assert!(graph.check(|index| index < 3).is_err());
+
+        // only a corrupt file can hold a cycle, walking it must still end
+        graph.set_link(0, link(2));
+        assert!(graph.check(|_| true).is_err());
+        assert!(!graph.creates_cycle(3, 1));
+        assert_eq!(graph.propagation_order(0), vec![1, 3, 2]);
+    }
+}
@@ -1,39 +1,59 @@
 mod widgets;
 pub mod util;
 pub mod gen;
-mod state;
+pub mod state;
+pub mod formats;
 
-use std::{path::Path, io, time::Duration};
+use std::{path::{Path, PathBuf}, io, time::Duration};
 
-use state::{State, Chan};
-use util::color::Color;
+use state::{State, Chan, Message, Index, to_repr};
+use util::{color::{Color, ColorDB, bundled_db, extend_db}, dictionary::Dictionary, loader::Loader, 
+           space::WorkingSpace, vision::Deficiency};
+use gen::relations::{Harmony, Relation};
+use formats::export::Stylesheet;
 
-use egui::{FontFamily, TextStyle, Ui, Layout, Align, pos2, vec2};
+use egui::{FontFamily, TextStyle, Ui, Key, Modifiers};
 
-use widgets::{ShadeStrip, ThreeStrip, ColorMap, ColorLabel};
+use widgets::{ThreeStrip, ColorMap, ContrastMatrix, VisionReport};
 
 pub struct Blush {
-    // db: VPTree<f64, NamedColor>,
+    db: Loader<ColorDB>,
+    // added to the bundled names, in increasing priority
+    dictionaries: Vec<Dictionary>,
     font: egui::FontId,
     state: State,
     chan: Chan,
     color_picker: ThreeStrip,
+    // the space the picker takes shades in
+    space: WorkingSpace,
+    // the color vision deficiency palette colors are drawn as seen with, if any
+    view: Option<Deficiency>,
     color_map: ColorMap,
-}
+    contrast_matrix: ContrastMatrix,
+    vision_report: VisionReport,
+
+    file: Option<PathBuf>,
+    file_input: String,
+    file_status: String,
+    export_input: String,
 
-#[derive(Debug, PartialEq)]
-enum Enum {
-    First,
-    Second,
-    Third,
+    harmony: Harmony,
+    link_source: Index,
+    link_relation: Relation,
+    name_input: String,
+    image_count: usize,
 }
+
 impl Blush {
 
     const UPDATE_MAX_INTERVAL: Duration = Duration::from_millis(500); // atleast one refresh in this time
+    const DEFAULT_FILE: &'static str = "palette.blush.json";
+    // priorities above the bundled names, which have 0
+    const CSS_PRIORITY: i32 = 1;
+    const USER_PRIORITY: i32 = 2;
 
     pub fn new(cc: &eframe::CreationContext) -> Result<Self, io::Error> {
         cc.egui_ctx.set_pixels_per_point(1.0f32);
-        // let db = util::color::load_db(Path::new("res/colors.json"))?;
         let mut fonts = egui::FontDefinitions::default();
         fonts.font_data.insert("uifont".into(), 
                                egui::FontData::from_static(include_bytes!("../res/Raleway-Regular.ttf")));
@@ -42,24 +62,302 @@ impl Blush {
 
         cc.egui_ctx.set_fonts(fonts);
 
+        let dictionaries = vec![Dictionary::css(Blush::CSS_PRIORITY)];
+
         Ok(Blush { 
-            // db,
+            db: Blush::load_db(dictionaries.clone()),
+            dictionaries,
             font: egui::FontId::new(30.0, FontFamily::Name("uifont".into())),
             state: State::default(),
             chan: Chan::default(),
-            color_picker: ThreeStrip::new(&Color::default()),
+            color_picker: ThreeStrip::new(&Color::default(), WorkingSpace::CieLch),
+            space: WorkingSpace::CieLch,
+            view: None,
             color_map: ColorMap::new(),
+            contrast_matrix: ContrastMatrix::new(),
+            vision_report: VisionReport::new(),
+
+            file: None,
+            file_input: Blush::DEFAULT_FILE.to_owned(),
+            file_status: String::new(),
+            export_input: Stylesheet::Css.file_name().to_owned(),
+
+            harmony: Harmony::Complementary,
+            link_source: 0,
+            link_relation: Relation::complement(),
+            name_input: String::new(),
+            image_count: formats::image::DEFAULT_COUNT,
+        })
+    }
+
+    fn load_db(dictionaries: Vec<Dictionary>) -> Loader<ColorDB> {
+        Loader::spawn(move || {
+            let mut db = bundled_db();
+            extend_db(&mut db, &dictionaries);
+            db
         })
     }
 
+    fn add_dictionary(&mut self, path: PathBuf) {
+        let priority = Blush::USER_PRIORITY + self.dictionaries.len() as i32;
+        let result = Dictionary::load(&path, priority).map(|dictionary| {
+            self.dictionaries.push(dictionary);
+            self.db = Blush::load_db(self.dictionaries.clone());
+        });
+        self.report("add dictionary", &path, result);
+    }
+
     fn apply_styles(&self, ui: &mut Ui) {
         ui.style_mut().text_styles.insert(TextStyle::Button, self.font.clone());
         ui.style_mut().text_styles.insert(TextStyle::Body, self.font.clone());
     }
+
+    // Undo and redo are left to text fields while one is focused.
+    fn key_bindings(&mut self, ctx: &egui::Context) {
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+        let mut input = ctx.input_mut();
+        if input.consume_key(Modifiers::COMMAND | Modifiers::SHIFT, Key::Z) {
+            self.chan.push(Message::Redo);
+        } else if input.consume_key(Modifiers::COMMAND, Key::Z) {
+            self.chan.push(Message::Undo);
+        }
+    }
+
+    fn space_tools(&mut self, ui: &mut Ui) {
+        egui::ComboBox::from_label("working space")
+            .selected_text(self.space.name())
+            .show_ui(ui, |ui| {
+                for space in WorkingSpace::ALL {
+                    ui.selectable_value(&mut self.space, space, space.name());
+                }
+            });
+        self.color_picker.set_space(&self.state.color_map().active_color(), self.space);
+    }
+
+    fn harmony_tools(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_label("harmony")
+                .selected_text(self.harmony.name())
+                .show_ui(ui, |ui| {
+                    for harmony in Harmony::ALL {
+                        ui.selectable_value(&mut self.harmony, harmony, harmony.name());
+                    }
+                });
+
+            let enabled = self.state.color_map().selected().is_some();
+            if ui.add_enabled(enabled, egui::Button::new("Fill row")).clicked() {
+                self.chan.push(Message::FillHarmony { harmony: self.harmony });
+            }
+        });
+    }
+
+    fn relation_tools(&mut self, ui: &mut Ui) {
+        let map = self.state.color_map();
+
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_label("derive from")
+                .selected_text(to_repr(self.link_source))
+                .show_ui(ui, |ui| {
+                    for index in map.indices() {
+                        ui.selectable_value(&mut self.link_source, index, to_repr(index));
+                    }
+                });
+
+            let kind = match self.link_relation {
+                Relation::Offset { .. } => "Offset",
+                Relation::Harmony { harmony, .. } => harmony.name(),
+            };
+            egui::ComboBox::from_id_source("relation")
+                .selected_text(kind)
+                .show_ui(ui, |ui| {
+                    let offset = Relation::Offset { luminance: 0.0, chroma: 0.0, hue: 0.0 };
+                    if ui.selectable_label(kind == "Offset", "Offset").clicked() {
+                        self.link_relation = offset;
+                    }
+                    for harmony in Harmony::ALL {
+                        if ui.selectable_label(kind == harmony.name(), harmony.name()).clicked() {
+                            self.link_relation = Relation::Harmony { harmony, member: 1 };
+                        }
+                    }
+                });
+
+            match &mut self.link_relation {
+                Relation::Offset { luminance, chroma, hue } => {
+                    for (value, label) in [(luminance, "L"), (chroma, "C"), (hue, "H")] {
+                        ui.label(label);
+                        ui.add(egui::DragValue::new(value).speed(0.01).clamp_range(-1.0..=1.0));
+                    }
+                }
+                Relation::Harmony { harmony, member } => {
+                    ui.label("member");
+                    ui.add(egui::DragValue::new(member).clamp_range(0..=harmony.size() - 1));
+                }
+            }
+
+            if ui.add_enabled(map.can_link(self.link_source), egui::Button::new("Link")).clicked() {
+                self.chan.push(Message::Link { source: self.link_source, relation: self.link_relation });
+            }
+
+            let linked = map.selected().and_then(|loc| map.link_at(loc)).is_some();
+            if ui.add_enabled(linked, egui::Button::new("Unlink")).clicked() {
+                self.chan.push(Message::Unlink);
+            }
+        });
+    }
+
+    fn name_tools(&mut self, ui: &mut Ui) {
+        let map = self.state.color_map();
+        let Some(index) = map.selected_index() else { return };
+
+        ui.horizontal(|ui| {
+            ui.label(format!("name of {}", to_repr(index)));
+            let response = ui.text_edit_singleline(&mut self.name_input);
+            let clicked = ui.button("Rename").clicked();
+            if response.lost_focus() || clicked {
+                self.chan.push(Message::Rename { name: self.name_input.clone() });
+            }
+            if !response.has_focus() {
+                self.name_input = map.name_of(index).unwrap_or_default().to_owned();
+            }
+        });
+    }
+
+    // Every stylesheet can be copied, and saved to a chosen path outside of the web.
+    fn export_menu(&mut self, ui: &mut Ui) {
+        ui.menu_button("Export", |ui| {
+            for sheet in Stylesheet::ALL {
+                ui.horizontal(|ui| {
+                    ui.label(sheet.name());
+                    if ui.button("Copy").clicked() {
+                        ui.output().copied_text = sheet.export(self.state.color_map());
+                        ui.close_menu();
+                    }
+                });
+            }
+
+            if cfg!(not(target_arch = "wasm32")) {
+                ui.separator();
+                // the stylesheet is picked from the extension
+                ui.text_edit_singleline(&mut self.export_input);
+                if ui.button("Save").clicked() {
+                    self.export(PathBuf::from(self.export_input.trim()));
+                    ui.close_menu();
+                }
+            }
+        });
+    }
+
+    // Stylesheets go through the same save path as palettes, but never replace an existing file.
+    fn export(&mut self, path: PathBuf) {
+        let result = match formats::Format::from_path(&path) {
+            _ if path.exists() => Err(io::Error::new(io::ErrorKind::AlreadyExists, "the file already exists")),
+            Some(formats::Format::Stylesheet(_)) => self.state.save(&path),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "not a .css, .scss or .js stylesheet")),
+        };
+        self.report("export", &path, result);
+    }
+
+    // Path typed in the file menu, with the document extension added if it has no known format.
+    fn input_path(&self) -> PathBuf {
+        let mut name = self.file_input.trim().to_owned();
+        if formats::Format::from_path(Path::new(&name)).is_none() {
+            name.push('.');
+            name.push_str(formats::document::EXTENSION);
+        }
+        PathBuf::from(name)
+    }
+
+    // Show the current file in the menu bar, or why `action` failed on it.
+    fn report(&mut self, action: &str, path: &Path, result: Result<(), io::Error>) -> bool {
+        match result {
+            Ok(()) => {
+                self.file_status = path.display().to_string();
+                true
+            }
+            Err(err) => {
+                self.file_status = format!("Could not {} {}: {}", action, path.display(), err);
+                false
+            }
+        }
+    }
+
+    fn open(&mut self, path: PathBuf) {
+        let result = self.state.open(&path);
+        if self.report("open", &path, result) {
+            self.file = Some(path);
+        }
+    }
+
+    fn save_as(&mut self, path: PathBuf) {
+        let result = self.state.save(&path);
+        if self.report("save", &path, result) {
+            self.file = Some(path);
+        }
+    }
+
+    // Pictures are imported with the chosen number of colors.
+    fn import_image(&mut self, path: PathBuf) {
+        let result = formats::image::load(&path, self.image_count)
+            .map(|map| self.state.replace(map));
+        self.report("import", &path, result);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn file_menu(&mut self, ui: &mut Ui) {
+        ui.menu_button("File", |ui| {
+            ui.text_edit_singleline(&mut self.file_input);
+
+            if ui.button("Open").clicked() {
+                self.open(self.input_path());
+                ui.close_menu();
+            }
+
+            if ui.button("Save").clicked() {
+                let path = self.file.clone().unwrap_or_else(|| self.input_path());
+                self.save_as(path);
+                ui.close_menu();
+            }
+
+            if ui.button("Save as").clicked() {
+                self.save_as(self.input_path());
+                ui.close_menu();
+            }
+
+            ui.separator();
+            ui.add(egui::Slider::new(&mut self.image_count, 1..=32).text("colors"));
+            if ui.button("Import picture").clicked() {
+                self.import_image(PathBuf::from(self.file_input.trim()));
+                ui.close_menu();
+            }
+            // .json, .csv or .gpl, its names win over the ones loaded before
+            if ui.button("Add dictionary").clicked() {
+                self.add_dictionary(PathBuf::from(self.file_input.trim()));
+                ui.close_menu();
+            }
+        });
+
+        ui.label(&self.file_status);
+    }
+
+    // There is no filesystem to save to on the web.
+    #[cfg(target_arch = "wasm32")]
+    fn file_menu(&mut self, _ui: &mut Ui) {}
 }
 
 impl eframe::App for Blush {
     fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        self.key_bindings(ctx);
+        self.db.poll();
+
+        egui::TopBottomPanel::top("menu").show(ctx, |ui| {
+            egui::menu::bar(ui, |ui| {
+                self.file_menu(ui);
+                self.export_menu(ui);
+            });
+        });
+
         let _ = egui::TopBottomPanel::top("colors").show(ctx, |ui| {
             
             ctx.request_repaint_after(Blush::UPDATE_MAX_INTERVAL);
@@ -84,7 +382,7 @@ impl eframe::App for Blush {
     
             egui::TopBottomPanel::top("color_pickers").show(ctx, |ui| {
                 ui.vertical_centered(|ui| {
-                    let response = ui.add(self.color_map.construct(self.state.color_map(), &mut self.chan, 
+                    let response = ui.add(self.color_map.construct(self.state.color_map(), self.db.get(), self.view, &mut self.chan, 
                                                                    max_size.x,
                                                                    max_size.y/2.0));
 
@@ -99,7 +397,7 @@ impl eframe::App for Blush {
                                 ui.vertical(|ui| {
                                     ui.label("Choose color:");
                                     self.color_picker.place(ui, self.state.color_map().active_color(),
-                                        &mut self.chan, 300.0, 300.0);
+                                        self.db.get(), &mut self.chan, 300.0, 300.0);
                                 });
                             });
 
@@ -118,7 +416,6 @@ impl eframe::App for Blush {
                     // }
 
 
-                    ui.add(ColorLabel::new().construct(Color::default(), "2".to_owned(), 100.0, 100.0));
 
                     // egui::popup::popup_below_widget(ui, colorpicker_id,
                     //                                 &response, |ui| {
@@ -130,15 +427,19 @@ impl eframe::App for Blush {
                     //     });
                     
 
-                    let mut selected = Enum::Second;
+                    self.space_tools(ui);
+                    self.harmony_tools(ui);
+                    self.relation_tools(ui);
+                    self.name_tools(ui);
+
+                    egui::CollapsingHeader::new("Contrast").default_open(true).show(ui, |ui| {
+                        self.contrast_matrix.place(ui, self.state.color_map(), self.view, &mut self.chan);
+                    });
 
-                    egui::ComboBox::from_label("Select one!")
-                        .selected_text(format!("{:?}", selected))
-                        .show_ui(ui, |ui| {
-                            ui.selectable_value(&mut selected, Enum::First, "First");
-                            ui.selectable_value(&mut selected, Enum::Second, "Second");
-                            ui.selectable_value(&mut selected, Enum::Third, "Third");
-                        });
+                    egui::CollapsingHeader::new("Color vision").show(ui, |ui| {
+                        self.vision_report.place(ui, self.state.color_map(), &mut self.view);
+                        self.color_picker.set_view(&self.state.color_map().active_color(), self.view);
+                    });
                 })
             });
 
@@ -0,0 +1,138 @@
+/* 
+ * Undo/redo history. Every step holds the edits that revert it, in the order they have to be
+ * applied. Consecutive steps recorded with `merge` set are folded together until the group is
+ * closed, so a whole color-picker interaction undoes at once.
+ * */
+
+use super::map_data::{MapData, Edit};
+
+#[derive(Default)]
+pub struct History {
+    undo: Vec<Vec<Edit>>,
+    redo: Vec<Vec<Edit>>,
+    merging: bool,
+}
+
+impl History {
+    // `inverse` are the edits returned while applying a change, in application order.
+    pub fn record(&mut self, inverse: Vec<Edit>, merge: bool) {
+        if inverse.is_empty() {
+            return;
+        }
+
+        self.redo.clear();
+
+        let mut inverse = inverse;
+        inverse.reverse();
+
+        match self.undo.last_mut() {
+            Some(step) if merge && self.merging => {
+                // the newer edits have to be reverted first
+                inverse.append(step);
+                *step = inverse;
+            }
+            _ => self.undo.push(inverse),
+        }
+
+        self.merging = merge;
+    }
+
+    // Stop merging into the last step.
+    pub fn close_group(&mut self) {
+        self.merging = false;
+    }
+
+    pub fn undo(&mut self, map: &mut MapData) {
+        self.close_group();
+        if let Some(step) = self.undo.pop() {
+            self.redo.push(Self::revert(map, step));
+        }
+    }
+
+    pub fn redo(&mut self, map: &mut MapData) {
+        self.close_group();
+        if let Some(step) = self.redo.pop() {
+            self.undo.push(Self::revert(map, step));
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    fn revert(map: &mut MapData, step: Vec<Edit>) -> Vec<Edit> {
+        let mut inverse: Vec<Edit> = step.into_iter().map(|edit| map.apply(edit)).collect();
+        inverse.reverse();
+        inverse
+    }
+}
+
+#[cfg(test)]
+mod history_tests {
+    use egui::pos2;
+
+    use super::History;
+    use crate::{state::MapData, util::color::Color, gen::relations::Relation};
+
+    #[test]
+    fn merged_changes_undo_at_once() {
+        let mut map = MapData::default();
+        let mut history = History::default();
+        let before = map.color_at((0, 0));
+
+        history.close_group();
+        map.start_change_color((0, 0), pos2(0.0, 0.0));
+        for lum in [0.1, 0.2, 0.3] {
+            let edits = map.update_color(Color { luminance: lum, ..Color::default() });
+            history.record(edits, true);
+        }
+
+        history.undo(&mut map);
+        assert_eq!(map.color_at((0, 0)), before);
+
+        history.redo(&mut map);
+        assert_eq!(map.color_at((0, 0)).unwrap().luminance, 0.3);
+    }
+
+    #[test]
+    fn undo_add_and_delete() {
+        let mut map = MapData::default();
+        let mut history = History::default();
+
+        let edits = map.add_color((3, 3), pos2(0.0, 0.0));
+        history.record(edits, true);
+        history.close_group();
+        let edits = map.delete_color((0, 0));
+        history.record(edits, false);
+
+        assert!(map.color_at((0, 0)).is_none());
+        history.undo(&mut map);
+        assert!(map.color_at((0, 0)).is_some());
+        history.undo(&mut map);
+        assert!(map.color_at((3, 3)).is_none());
+    }
+
+    #[test]
+    fn links_propagate_and_undo() {
+        let mut map = MapData::default();
+        let mut history = History::default();
+
+        // derive (1, 0) from (0, 0)
+        map.start_change_color((1, 0), pos2(0.0, 0.0));
+        let source = map.indices()[0];
+        let edits = map.link(source, Relation::complement());
+        history.record(edits, false);
+        assert!(map.link_at((1, 0)).is_some());
+
+        map.start_change_color((0, 0), pos2(0.0, 0.0));
+        assert!(!map.can_link(map.indices()[1]));
+        let edits = map.update_color(Color::default());
+        history.record(edits, true);
+        assert_eq!(map.color_at((1, 0)), Some(Relation::complement().derive(Color::default())));
+
+        history.undo(&mut map);
+        history.undo(&mut map);
+        assert!(map.link_at((1, 0)).is_none());
+        assert_eq!(map.color_at((1, 0)), MapData::default().color_at((1, 0)));
+    }
+}
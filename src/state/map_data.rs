@@ -1,8 +1,9 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, BTreeMap};
 
 use egui::{Pos2, pos2};
+use serde::{Serialize, Deserialize};
 
-use crate::util::color::Color;
+use crate::{util::color::Color, gen::relations::{Harmony, Link, Relation, RelationGraph}};
 
 pub type Index = usize;
 pub type IndexRepr = String;
@@ -13,16 +14,58 @@ pub fn to_repr(idx: Index) -> IndexRepr {
 
 pub type Location = (usize, usize);
 
+// A primitive change to the palette, every higher level operation on `MapData` is a sequence of
+// these. `None` removes the entry.
+#[derive(Clone, Debug, PartialEq)]
+#[allow(clippy::enum_variant_names)]
+pub enum Edit {
+    SetCell { loc: Location, index: Option<Index> },
+    SetColor { index: Index, color: Option<Color> },
+    SetLink { index: Index, link: Option<Link> },
+    SetName { index: Index, name: Option<String> },
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct MapData {
     rows: usize,
     cols: usize,
+    #[serde(with = "sorted_pairs")]
     map: HashMap<Location, Index>,
+    #[serde(with = "sorted_pairs")]
     colors: HashMap<Index, Color>,
+    relations: RelationGraph,
+    names: BTreeMap<Index, String>,
     next_color: Index,
     
+    #[serde(skip)]
     choose_color_mode: bool,
+    #[serde(skip)]
     choose_color_pos: Pos2,
+    #[serde(skip)]
     choose_color_idx: Index,
+    #[serde(skip)]
+    selected: Option<Location>,
+}
+
+// HashMaps with tuple keys can't be json objects, so maps are stored as a list of (key, value)
+// pairs instead, sorted so that saving the same palette twice gives the same file.
+mod sorted_pairs {
+    use std::{collections::HashMap, hash::Hash};
+
+    use serde::{Serialize, Serializer, Deserialize, Deserializer};
+
+    pub fn serialize<K, V, S>(map: &HashMap<K, V>, serializer: S) -> Result<S::Ok, S::Error> 
+    where K: Serialize + Ord, V: Serialize, S: Serializer {
+        let mut pairs: Vec<(&K, &V)> = map.iter().collect();
+        pairs.sort_by(|a, b| a.0.cmp(b.0));
+        pairs.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, K, V, D>(deserializer: D) -> Result<HashMap<K, V>, D::Error> 
+    where K: Deserialize<'de> + Eq + Hash, V: Deserialize<'de>, D: Deserializer<'de> {
+        let pairs: Vec<(K, V)> = Vec::deserialize(deserializer)?;
+        Ok(pairs.into_iter().collect())
+    }
 }
 
 impl Default for MapData {
@@ -40,16 +83,53 @@ impl Default for MapData {
                                   (1, Color::from_hex("#c8d3d5")),
                                   (2, Color::from_hex("#a4b8c4"))
             ]),
+            relations: RelationGraph::default(),
+            names: BTreeMap::new(),
             next_color: 3,
 
             choose_color_mode: false,
             choose_color_pos: pos2(0.0, 0.0),
-            choose_color_idx: 0
+            choose_color_idx: 0,
+            selected: None,
         }
     }
 }
 
 impl MapData {
+    // A palette holding `cells`, indexed in the order they are given.
+    pub fn from_cells(rows: usize, cols: usize, cells: Vec<(Location, Color, Option<String>)>) -> Self {
+        let mut map = MapData {
+            rows,
+            cols,
+            map: HashMap::new(),
+            colors: HashMap::new(),
+            names: BTreeMap::new(),
+            next_color: 0,
+            ..MapData::default()
+        };
+
+        for (loc, color, name) in cells {
+            let index = map.next_color;
+            map.next_color += 1;
+            map.map.insert(loc, index);
+            map.colors.insert(index, color);
+            if let Some(name) = name {
+                map.names.insert(index, name);
+            }
+        }
+
+        map
+    }
+
+    // Check a palette read from a file. Its relations must be acyclic and between stored colors,
+    // and it may claim a `next_color` already in use, new colors would then overwrite an existing one.
+    pub(crate) fn loaded(mut self) -> Result<Self, String> {
+        self.relations.check(|index| self.colors.contains_key(&index))?;
+        let used = self.colors.keys().chain(self.map.values()).max().map_or(0, |index| index + 1);
+        self.next_color = self.next_color.max(used);
+        Ok(self)
+    }
+
     pub fn size(&self) -> (usize, usize) {
         (self.rows, self.cols)
     }
@@ -63,26 +143,169 @@ impl MapData {
         self.colors.get(index).copied()
     }
 
-    pub(super) fn add_color(&mut self, loc: Location, pos: Pos2) {
-        self.colors.insert(self.next_color, Color::default());
-        self.map.insert(loc, self.next_color);
+    // Apply a primitive edit, returning the edit that reverts it.
+    pub(super) fn apply(&mut self, edit: Edit) -> Edit {
+        match edit {
+            Edit::SetCell { loc, index } => {
+                let old = match index {
+                    Some(index) => self.map.insert(loc, index),
+                    None => self.map.remove(&loc),
+                };
+                Edit::SetCell { loc, index: old }
+            }
+
+            Edit::SetColor { index, color } => {
+                let old = match color {
+                    Some(color) => self.colors.insert(index, color),
+                    None => self.colors.remove(&index),
+                };
+                Edit::SetColor { index, color: old }
+            }
+
+            Edit::SetLink { index, link } => {
+                Edit::SetLink { index, link: self.relations.set_link(index, link) }
+            }
+
+            Edit::SetName { index, name } => {
+                let old = match name {
+                    Some(name) => self.names.insert(index, name),
+                    None => self.names.remove(&index),
+                };
+                Edit::SetName { index, name: old }
+            }
+        }
+    }
+
+    fn apply_all(&mut self, edits: Vec<Edit>) -> Vec<Edit> {
+        edits.into_iter().map(|edit| self.apply(edit)).collect()
+    }
+
+    pub(super) fn add_color(&mut self, loc: Location, pos: Pos2) -> Vec<Edit> {
+        let index = self.next_color;
         self.next_color += 1;
+        let undo = self.apply_all(vec![
+            Edit::SetColor { index, color: Some(Color::default()) },
+            Edit::SetCell { loc, index: Some(index) }
+        ]);
         self.start_change_color(loc, pos);
+        undo
     }
 
     pub(super) fn start_change_color(&mut self, loc: Location, pos: Pos2) {
         self.choose_color_idx = *self.map.get(&loc).unwrap();
+        self.selected = Some(loc);
         self.choose_color_pos = pos;
         self.choose_color_mode = true;
     }
 
-    pub(super) fn update_color(&mut self, to: Color) {
-        self.colors.insert(self.choose_color_idx, to);
+    pub(super) fn update_color(&mut self, to: Color) -> Vec<Edit> {
+        self.set_color(self.choose_color_idx, to)
+    }
+
+    // Set the color of any cell, not only the one being picked. Like every color set by hand, a
+    // derived cell stops being derived.
+    pub fn recolor(&mut self, index: Index, color: Color) -> Vec<Edit> {
+        self.set_color(index, color)
+    }
+
+    // Set a color by hand: the cell stops being derived, and its dependents follow it.
+    fn set_color(&mut self, index: Index, color: Color) -> Vec<Edit> {
+        let mut undo = vec![self.apply(Edit::SetColor { index, color: Some(color) })];
+        if self.relations.link(index).is_some() {
+            undo.push(self.apply(Edit::SetLink { index, link: None }));
+        }
+        undo.extend(self.propagate(index));
+        undo
+    }
+
+    // Recompute every cell derived from `index`, directly or not.
+    fn propagate(&mut self, index: Index) -> Vec<Edit> {
+        let mut undo = vec![];
+        for dep in self.relations.propagation_order(index) {
+            let Link { source, relation } = self.relations.link(dep).unwrap();
+            if let Some(source) = self.colors.get(&source).copied() {
+                undo.push(self.apply(Edit::SetColor { index: dep, color: Some(relation.derive(source)) }));
+            }
+        }
+        undo
+    }
+
+    // The selected cell can be derived from `source` without depending on itself.
+    pub fn can_link(&self, source: Index) -> bool {
+        match self.selected_index() {
+            Some(target) => self.colors.contains_key(&source) && !self.relations.creates_cycle(target, source),
+            None => false,
+        }
+    }
+
+    pub(super) fn link(&mut self, source: Index, relation: Relation) -> Vec<Edit> {
+        let Some(target) = self.selected_index() else { return vec![] };
+        if !self.can_link(source) {
+            return vec![];
+        }
+
+        let mut undo = vec![self.apply(Edit::SetLink { index: target, link: Some(Link { source, relation }) })];
+        let color = relation.derive(self.colors[&source]);
+        undo.push(self.apply(Edit::SetColor { index: target, color: Some(color) }));
+        undo.extend(self.propagate(target));
+        undo
+    }
+
+    // Name the selected cell, an empty name goes back to the index.
+    pub(super) fn rename(&mut self, name: String) -> Vec<Edit> {
+        let Some(index) = self.selected_index() else { return vec![] };
+        let name = Some(name.trim().to_owned()).filter(|name| !name.is_empty());
+        if self.names.get(&index) == name.as_ref() {
+            return vec![];
+        }
+        vec![self.apply(Edit::SetName { index, name })]
+    }
+
+    pub(super) fn unlink(&mut self) -> Vec<Edit> {
+        match self.selected_index() {
+            Some(index) if self.relations.link(index).is_some() => 
+                vec![self.apply(Edit::SetLink { index, link: None })],
+            _ => vec![],
+        }
+    }
+
+    // Fill the cells right of the selected one with the rest of its harmony, reusing the indices
+    // of cells that are already there. Members that don't fit in the row are dropped.
+    pub(super) fn fill_harmony(&mut self, harmony: Harmony) -> Vec<Edit> {
+        let Some((r, c)) = self.selected else { return vec![] };
+        let Some(base) = self.color_at((r, c)) else { return vec![] };
+
+        let mut undo = vec![];
+        let members = harmony.colors(base).into_iter().skip(1);
+        for (loc, color) in ((c + 1)..self.cols).map(|c| (r, c)).zip(members) {
+            let index = match self.map.get(&loc) {
+                Some(index) => *index,
+                None => {
+                    let index = self.next_color;
+                    self.next_color += 1;
+                    undo.push(self.apply(Edit::SetCell { loc, index: Some(index) }));
+                    index
+                }
+            };
+            undo.extend(self.set_color(index, color));
+        }
+        undo
     }
 
-    pub(super) fn delete_color(&mut self, loc: Location) {
-        let idx = self.map.get(&loc).unwrap();
-        self.colors.remove(idx);
+    pub(super) fn delete_color(&mut self, loc: Location) -> Vec<Edit> {
+        let index = *self.map.get(&loc).unwrap();
+
+        // dependents keep their last color
+        let mut edits: Vec<Edit> = self.relations.dependents(index).into_iter()
+            .map(|dep| Edit::SetLink { index: dep, link: None })
+            .collect();
+        edits.extend([
+            Edit::SetName { index, name: None },
+            Edit::SetLink { index, link: None },
+            Edit::SetColor { index, color: None },
+            Edit::SetCell { loc, index: None }
+        ]);
+        self.apply_all(edits)
     }
 
     pub(super) fn distracted(&mut self) {
@@ -97,6 +320,46 @@ impl MapData {
         self.choose_color_pos
     }
 
+    pub fn selected(&self) -> Option<Location> {
+        self.selected
+    }
+
+    pub fn selected_index(&self) -> Option<Index> {
+        self.map.get(&self.selected?).copied()
+    }
+
+    pub fn link_at(&self, loc: Location) -> Option<Link> {
+        self.relations.link(*self.map.get(&loc)?)
+    }
+
+    pub fn name_of(&self, index: Index) -> Option<&str> {
+        self.names.get(&index).map(String::as_str)
+    }
+
+    // The user-assigned name of a cell, or its index.
+    pub fn label_of(&self, index: Index) -> String {
+        self.name_of(index).map_or_else(|| to_repr(index), str::to_owned)
+    }
+
+    // All cells with a color, in row-major order.
+    pub fn cells(&self) -> Vec<(Location, Index, Color)> {
+        let mut cells: Vec<(Location, Index, Color)> = self.map.iter()
+            .filter_map(|(loc, index)| Some((*loc, *index, *self.colors.get(index)?)))
+            .collect();
+        cells.sort_by_key(|(loc, _, _)| *loc);
+        cells
+    }
+
+    // Indices of all cells with a color, in order of creation.
+    pub fn indices(&self) -> Vec<Index> {
+        let mut indices: Vec<Index> = self.map.values()
+            .filter(|index| self.colors.contains_key(index))
+            .copied()
+            .collect();
+        indices.sort();
+        indices
+    }
+
     pub fn active_color(&self) -> Color {
         *self.colors.get(&self.choose_color_idx).unwrap_or(&Color::default())
     }
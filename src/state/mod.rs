@@ -1,22 +1,26 @@
-use std::{collections::HashMap};
+use std::{path::Path, io};
 
-use crate::util::{color::Color, buffer::Buffer};
+use crate::{util::{color::Color, buffer::Buffer}, formats, gen::relations::{Harmony, Relation}};
 
 mod map_data;
-pub use map_data::IndexRepr;
+mod history;
+pub use map_data::{Index, IndexRepr, to_repr};
 
 use egui::Pos2;
 pub use map_data::MapData;
 pub use map_data::Location;
 
+use history::History;
+
 pub type Chan = Buffer<Message>;
 
 #[derive(Default)]
 pub struct State {
     color_map: map_data::MapData,
+    history: History,
 }
 
-#[derive(Default, Clone, Copy, Debug)]
+#[derive(Default, Clone, Debug)]
 pub enum Message {
     #[default] NoOp,
 
@@ -26,6 +30,17 @@ pub enum Message {
     AddColor { loc: (usize, usize), pos: Pos2 },
     UpdateColor { loc: (usize, usize), pos: Pos2 },
     DeleteColor { loc: (usize, usize) },
+
+    // From the tools panel
+    FillHarmony { harmony: Harmony },
+    Link { source: Index, relation: Relation },
+    Unlink,
+    Rename { name: String },
+    // Change the color of a given cell as one undo step, leaving the selection alone.
+    Recolor { index: Index, to: Color },
+
+    Undo,
+    Redo,
 }
 
 impl State {
@@ -46,25 +61,72 @@ impl State {
             // From three-strip ////////// 
 
             ChangeColor { to } => {
-                self.color_map.update_color(to);
+                let edits = self.color_map.update_color(to);
+                self.history.record(edits, true);
             }
 
             Distracted => {
                 self.color_map.distracted();
+                self.history.close_group();
             }
         
             // From color-map ///////////
             
             AddColor { loc, pos } => {
-                self.color_map.add_color(loc, pos);
+                // the color chosen right after adding belongs to the same undo step
+                self.history.close_group();
+                let edits = self.color_map.add_color(loc, pos);
+                self.history.record(edits, true);
             }
             
             UpdateColor { loc, pos } => {
+                self.history.close_group();
                 self.color_map.start_change_color(loc, pos);
             }
 
             DeleteColor { loc } => {
-                self.color_map.delete_color(loc);
+                let edits = self.color_map.delete_color(loc);
+                self.history.record(edits, false);
+            }
+
+            // From tools panel //////////
+
+            FillHarmony { harmony } => {
+                let edits = self.color_map.fill_harmony(harmony);
+                self.history.record(edits, false);
+            }
+
+            Link { source, relation } => {
+                let edits = self.color_map.link(source, relation);
+                self.history.record(edits, false);
+            }
+
+            Unlink => {
+                let edits = self.color_map.unlink();
+                self.history.record(edits, false);
+            }
+
+            Rename { name } => {
+                let edits = self.color_map.rename(name);
+                self.history.record(edits, false);
+            }
+
+            Recolor { index, to } => {
+                self.history.close_group();
+                let edits = self.color_map.recolor(index, to);
+                self.history.record(edits, false);
+            }
+
+            // From key bindings ////////
+
+            Undo => {
+                self.color_map.distracted();
+                self.history.undo(&mut self.color_map);
+            }
+
+            Redo => {
+                self.color_map.distracted();
+                self.history.redo(&mut self.color_map);
             }
         }
     }
@@ -72,6 +134,21 @@ impl State {
     pub fn color_map(&self) -> &map_data::MapData {
         &self.color_map
     }
+
+    pub(super) fn open(&mut self, path: &Path) -> Result<(), io::Error> {
+        self.replace(formats::load(path)?);
+        Ok(())
+    }
+
+    // Start over from another palette, the history doesn't apply to it.
+    pub(super) fn replace(&mut self, map: MapData) {
+        self.color_map = map;
+        self.history.clear();
+    }
+
+    pub(super) fn save(&self, path: &Path) -> Result<(), io::Error> {
+        formats::save(&self.color_map, path)
+    }
 }
 
 
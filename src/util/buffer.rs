@@ -52,7 +52,6 @@ impl<T: Default + Clone> Default for Buffer<T> {
 }
 
 #[cfg(test)]
-
 mod queue_tests {
     use super::Buffer;
 
@@ -1,17 +1,27 @@
-/* A tristimulus color space; uses lch for perceptual uniformity, but normalized to 0 - 1 */
+/* A tristimulus color space; uses lch for perceptual uniformity, but normalized to 0 - 1, with an
+ * opacity from 0 (transparent) to 1 */
 
-use std::{path::Path, fs, io};
+use std::{path::Path, io, marker::PhantomData, sync::Arc, cmp::Reverse};
 
 use egui::Color32;
-use palette::{FromColor, Srgb, Lch};
+use palette::{FromColor, Srgb, LinSrgb, Lch};
+use serde::{Serialize, Deserialize};
 
-use super::vptree::{VPTree, MetricPoint};
+use super::{vptree::{VPTree, MetricPoint, FlatNode}, metric::{ColorMetric, Ciede2000}, dictionary::Dictionary,
+            vision::Deficiency, space::{self, WorkingSpace}, notation};
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Color {
     pub luminance: f32,
     pub chroma: f32,
-    pub hue: f32
+    pub hue: f32,
+    // palettes saved before colors had opacity are opaque
+    #[serde(default = "opaque")]
+    pub alpha: f32,
+}
+
+fn opaque() -> f32 {
+    1.0
 }
 
 impl Default for Color {
@@ -21,45 +31,82 @@ impl Default for Color {
 }
 
 impl Color {
+    // For hex literals, in any CSS length and with or without the '#'. Text from users is better
+    // `parse`d, which says what is wrong with it instead of panicking.
     pub fn from_hex(hex: &str) -> Self {
-        let srgb =
-            Srgb::from(u32::from_str_radix(&hex[1..], 16).unwrap());
-        let srgb: Srgb<f32> = Srgb::from_format(srgb);
+        match notation::parse_hex(hex) {
+            Ok(color) => color,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    pub fn from_rgb(r: u8, g: u8, b: u8) -> Self {
+        Color::from_srgb(Srgb::new(r, g, b).into_format())
+    }
+
+    pub(super) fn from_srgb(srgb: Srgb<f32>) -> Self {
+        Color::from_lch(Lch::from_color(srgb))
+    }
 
-        let lch = Lch::from_color(srgb);
+    pub(super) fn from_lch(lch: Lch) -> Self {
         let (l, c, h) = lch.into_components();
 
         Color {
             hue: h.to_positive_degrees()/360.0,
             luminance: l/100.0,
-            chroma: c/100.0
+            chroma: c/100.0,
+            alpha: 1.0,
         }
     }
 
-    pub fn to_hex(self) -> String {
-        let Color { luminance, chroma, hue } = self;
-        let lch = Lch::from_components((luminance * 100.0, chroma * 100.0, hue * 360.0));
-        let srgb: Srgb<u8> = Srgb::from_color(lch).into_format();
+    pub(super) fn lch(self) -> Lch {
+        let Color { luminance, chroma, hue, .. } = self;
+        Lch::from_components((luminance * 100.0, chroma * 100.0, hue * 360.0))
+    }
+
+    // Whether sRGB can show the color, otherwise it is gamut mapped when drawn.
+    pub fn in_gamut(self) -> bool {
+        space::in_gamut(self)
+    }
+
+    // Gamut mapped sRGB channels, without the opacity.
+    pub fn to_rgb(self) -> [u8; 3] {
+        let srgb: Srgb<u8> = space::gamut_map(self).into_format();
         let (r, g, b) = srgb.into_components();
+        [r, g, b]
+    }
+
+    fn alpha_u8(self) -> u8 {
+        (self.alpha.clamp(0.0, 1.0) * 255.0).round() as u8
+    }
 
-        let hex = (r as u32) * 256 * 256 + (g as u32) * 256 + (b as u32); 
-        let mut s = format!("{:06x}", hex);
-        s.insert(0, '#');
-        s
+    // "#rrggbb", or "#rrggbbaa" if the color is not opaque.
+    pub fn to_hex(self) -> String {
+        let [r, g, b] = self.to_rgb();
+        match self.alpha_u8() {
+            255 => format!("#{:02x}{:02x}{:02x}", r, g, b),
+            a => format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a),
+        }
     }
 
     pub fn to_color32(self) -> Color32 {
-        let Color { luminance, chroma, hue } = self;
-        
-        let lch = Lch::from_components((luminance * 100.0, chroma * 100.0, hue * 360.0));
-        let srgb = Srgb::from_color(lch).into_format();
-        let (r, g, b) = srgb.into_components();
+        let [r, g, b] = self.to_rgb();
 
-        Color32::from_rgb(r, g, b)
+        // egui blends premultiplied colors
+        Color32::from_rgba_unmultiplied(r, g, b, self.alpha_u8())
+    }
+
+    // The color as seen with `view`, if any.
+    pub fn seen_as(self, view: Option<Deficiency>) -> Color {
+        view.map_or(self, |deficiency| self.simulate(deficiency))
+    }
+
+    pub fn to_color32_as(self, view: Option<Deficiency>) -> Color32 {
+        self.seen_as(view).to_color32()
     }
 
     pub fn rotate(self, amount: f32) -> Color {
-        Color{ luminance: self.luminance, chroma: self.chroma, hue: (self.hue + amount + 1.0) % 1.0 }
+        Color{ hue: (self.hue + amount + 1.0) % 1.0, ..self }
     }
 
 
@@ -74,19 +121,202 @@ impl Color {
     }
 
     // Return a color that is legible and nice when placed on another color, preferably a shade of
-    // the color.
+    // the color. It is opaque, to stay legible on translucent colors.
     pub fn accent_color(self) -> Color {
-        let Color{luminance, chroma, hue} = self;
+        let Color{luminance, chroma, hue, ..} = self;
         
         let luminance = (luminance + 0.4) % 1.0;
         // let hue = 
 
-        Color{luminance, chroma, hue}
+        Color{luminance, chroma, hue, alpha: 1.0}
     }
 
     pub fn accent(self) -> Color32 {
         self.accent_color().to_color32()
     }
+
+    // Gamma encoded sRGB channels, gamut mapped.
+    fn srgb(self) -> [f32; 3] {
+        let (r, g, b) = space::gamut_map(self).into_components();
+        [r, g, b]
+    }
+
+    // How the color looks with `deficiency`.
+    pub fn simulate(self, deficiency: Deficiency) -> Color {
+        let [r, g, b] = self.srgb();
+        let (r, g, b) = Srgb::new(r, g, b).into_linear().into_components();
+        let [r, g, b] = deficiency.apply([r, g, b]);
+        Color { alpha: self.alpha, ..Color::from_srgb(Srgb::from_linear(LinSrgb::new(r, g, b))) }
+    }
+
+    // WCAG 2.x relative luminance, of the color as if it were opaque.
+    pub fn relative_luminance(self) -> f32 {
+        let linear = |x: f32| if x <= 0.03928 { x / 12.92 } else { ((x + 0.055) / 1.055).powf(2.4) };
+        let [r, g, b] = self.srgb().map(linear);
+        0.2126 * r + 0.7152 * g + 0.0722 * b
+    }
+
+    // WCAG 2.x contrast ratio, from 1 to 21, the same either way round.
+    pub fn contrast_ratio(self, other: Color) -> f32 {
+        let (a, b) = (self.relative_luminance(), other.relative_luminance());
+        (a.max(b) + 0.05) / (a.min(b) + 0.05)
+    }
+
+    // APCA lightness contrast Lc of `self` as text on `background` (0.0.98G-4g constants): 
+    // positive for dark text on a light background, negative the other way round.
+    pub fn apca_contrast(self, background: Color) -> f32 {
+        const BLACK_THRESHOLD: f32 = 0.022;
+        const BLACK_CLAMP: f32 = 1.414;
+        const DELTA_Y_MIN: f32 = 0.0005;
+        const SCALE: f32 = 1.14;
+        const OFFSET: f32 = 0.027;
+        const LOW_CLIP: f32 = 0.1;
+
+        let screen_luminance = |color: Color| {
+            let [r, g, b] = color.srgb().map(|x| x.powf(2.4));
+            let y = 0.2126729 * r + 0.7151522 * g + 0.0721750 * b;
+            if y <= BLACK_THRESHOLD { y + (BLACK_THRESHOLD - y).powf(BLACK_CLAMP) } else { y }
+        };
+        let (text, background) = (screen_luminance(self), screen_luminance(background));
+
+        if (background - text).abs() < DELTA_Y_MIN {
+            return 0.0;
+        }
+        let lc = if background > text {
+            let sapc = (background.powf(0.56) - text.powf(0.57)) * SCALE;
+            if sapc < LOW_CLIP { 0.0 } else { sapc - OFFSET }
+        } else {
+            let sapc = (background.powf(0.65) - text.powf(0.62)) * SCALE;
+            if sapc > -LOW_CLIP { 0.0 } else { sapc + OFFSET }
+        };
+        lc * 100.0
+    }
+}
+
+// Luminance steps searched by `fix_contrast`.
+const FIX_CONTRAST_STEPS: usize = 1000;
+
+// The color closest in luminance to `color`, with its hue and chroma, that has at least
+// `target` contrast ratio against `background`; `None` if no luminance gets there.
+pub fn fix_contrast(color: Color, background: Color, target: f32) -> Option<Color> {
+    let lerp = luminance_lerp(WorkingSpace::CieLch, 0.0, 1.0);
+    let start = (lerp.position)(color);
+    let passes = |t: f32| {
+        let candidate = (lerp.lerp)(color, t);
+        (candidate.contrast_ratio(background) >= target).then_some(candidate)
+    };
+
+    // step outwards from the current luminance, both ways at once
+    (0..=FIX_CONTRAST_STEPS)
+        .map(|step| step as f32 / FIX_CONTRAST_STEPS as f32)
+        .find_map(|delta| {
+            let lighter = Some(start + delta).filter(|t| *t <= 1.0).and_then(passes);
+            let darker = Some(start - delta).filter(|t| *t >= 0.0).and_then(passes);
+            match (lighter, darker) {
+                (Some(l), Some(d)) => Some(if l.contrast_ratio(background) >= d.contrast_ratio(background) { l } else { d }),
+                (l, d) => l.or(d),
+            }
+        })
+}
+
+// WCAG 2.x contrast levels, success criteria 1.4.3 (AA) and 1.4.6 (AAA). Large text is at least
+// 18pt, or 14pt bold.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContrastLevel {
+    AaLarge,
+    Aa,
+    AaaLarge,
+    Aaa,
+}
+
+impl ContrastLevel {
+    pub const ALL: [ContrastLevel; 4] = [ContrastLevel::Aa, ContrastLevel::Aaa,
+                                         ContrastLevel::AaLarge, ContrastLevel::AaaLarge];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ContrastLevel::AaLarge => "AA large",
+            ContrastLevel::Aa => "AA",
+            ContrastLevel::AaaLarge => "AAA large",
+            ContrastLevel::Aaa => "AAA",
+        }
+    }
+
+    // For badges, where the full name does not fit.
+    pub fn short_name(self) -> &'static str {
+        match self {
+            ContrastLevel::AaLarge => "AA L",
+            ContrastLevel::Aa => "AA",
+            ContrastLevel::AaaLarge => "AAA L",
+            ContrastLevel::Aaa => "AAA",
+        }
+    }
+
+    pub fn min_ratio(self) -> f32 {
+        match self {
+            ContrastLevel::AaLarge => 3.0,
+            ContrastLevel::Aa | ContrastLevel::AaaLarge => 4.5,
+            ContrastLevel::Aaa => 7.0,
+        }
+    }
+
+    pub fn passes(self, ratio: f32) -> bool {
+        ratio >= self.min_ratio()
+    }
+}
+
+#[test]
+fn test_contrast() {
+    let (black, white) = (Color::from_hex("#000000"), Color::from_hex("#ffffff"));
+    assert!((black.contrast_ratio(white) - 21.0).abs() < 0.05);
+    assert!((white.contrast_ratio(white) - 1.0).abs() < 0.01);
+    let grey = Color::from_hex("#777777");
+    assert!((grey.contrast_ratio(white) - 4.48).abs() < 0.02);
+    assert!(!ContrastLevel::Aa.passes(grey.contrast_ratio(white)));
+    assert!(ContrastLevel::AaLarge.passes(grey.contrast_ratio(white)));
+
+    let fixed = fix_contrast(grey, white, 4.5).unwrap();
+    assert!(fixed.contrast_ratio(white) >= 4.5);
+    assert!(fixed.luminance < grey.luminance && grey.luminance - fixed.luminance < 0.01);
+    assert_eq!((fixed.hue, fixed.chroma), (grey.hue, grey.chroma));
+    assert_eq!(fix_contrast(white, white, 1.0), Some(white));
+    assert!(fix_contrast(grey, grey, 21.5).is_none());
+
+    // reference values of the APCA implementation
+    let grey = Color::from_hex("#888888");
+    assert!((grey.apca_contrast(white) - 63.06).abs() < 0.1);
+    assert!((white.apca_contrast(grey) + 68.54).abs() < 0.1);
+    assert!((black.apca_contrast(white) - 106.04).abs() < 0.1);
+    assert!((white.apca_contrast(black) + 107.88).abs() < 0.1);
+}
+
+#[test]
+fn test_alpha() {
+    let scrim = Color::from_hex("#de5d8380");
+    assert_eq!(scrim.to_hex(), "#de5d8380");
+    assert_eq!(Color { alpha: 1.0, ..scrim }.to_hex(), "#de5d83");
+    // premultiplied for egui, in linear space
+    let red = Color::from_hex("#ff000080").to_color32();
+    assert_eq!(red, Color32::from_rgba_unmultiplied(255, 0, 0, 128));
+    assert_eq!((red.r(), red.a()), (188, 128));
+
+    let saved: Color = serde_json::from_str(r#"{"luminance": 0.5, "chroma": 0.1, "hue": 0.2}"#).unwrap();
+    assert_eq!(saved.alpha, 1.0);
+    let shades = shades(scrim, 10, &alpha_lerp(0.0, 1.0)).0;
+    assert!(shades.iter().all(|shade| shade.to_rgb() == scrim.to_rgb()));
+}
+
+#[test]
+fn test_shades_at_end() {
+    let space = WorkingSpace::CieLch;
+    for (color, lerp) in [(Color::from_hex("#de5d83"), alpha_lerp(0.0, 1.0)),
+                          (Color::from_hex("#ffffff"), luminance_lerp(space, 0.0, 1.0))] {
+        for count in [4, 10] {
+            let (shades, index) = shades(color, count, &lerp);
+            assert_eq!(index, count - 1);
+            assert_eq!(shades[index].to_hex(), color.to_hex());
+        }
+    }
 }
 
 #[test]
@@ -95,39 +325,55 @@ fn test_inverse_color() {
     assert_eq!(hex, Color::from_hex(hex).to_hex());
 }
 
+#[test]
+fn test_db_metrics() {
+    use super::metric::{Cie76, Cie94};
+
+    let path = Path::new("res/colors.json");
+    let alice_blue = Color::from_hex("#f0f8ff");
+    assert_eq!(quantize_color(&load_db::<Cie76>(path).unwrap(), alice_blue).name(), "Alice Blue");
+    assert_eq!(quantize_color(&load_db::<Cie94>(path).unwrap(), alice_blue).name(), "Alice Blue");
+    assert_eq!(quantize_color(&load_db::<Ciede2000>(path).unwrap(), alice_blue).name(), "Alice Blue");
+}
 
-/* Bad implementation for color distance, ideally one should convert to Luv and then compute
- * distances */
+
+/* Colors are compared with CIEDE2000 by default, use `metric::Measured` for another metric */
 impl MetricPoint for Color {
     type Dist = f32;
 
     fn dist(from: &Self, to: &Self) -> Self::Dist {
-        let Color{luminance: l1, chroma: c1, hue: h1} = from;
-        let Color{luminance: l2, chroma: c2, hue: h2} = to;
-        
-        let hdist = (h1 - h2).abs().min(1. - (h1 - h2).abs());
-        hdist + (c2 - c1).abs() + (l2 - l1).abs()
+        Ciede2000::distance(from, to)
     }
 }
 
-pub fn luminance_lerp(start: f32, end: f32) -> Lerp {
+// Moves along one axis of the working space: 0 for luminance, 1 for chroma and 2 for hue.
+fn axis_lerp(space: WorkingSpace, axis: usize, start: f32, end: f32) -> Lerp {
     Lerp{
-        lerp: Box::new(move |color: Color, t| Color{luminance: start + (end - start) * t, ..color}),
-        position: Box::new(move |color: Color| (color.luminance - start)/(end - start))
+        lerp: Box::new(move |color: Color, t| {
+            let mut components = space.components(color);
+            components[axis] = start + (end - start) * t;
+            Color { alpha: color.alpha, ..space.color(components) }
+        }),
+        position: Box::new(move |color: Color| (space.components(color)[axis] - start)/(end - start))
     }
 }
 
-pub fn chroma_lerp(start: f32, end: f32) -> Lerp {
-    Lerp{
-        lerp: Box::new(move |color: Color, t| Color{chroma: start + (end - start) * t, ..color}),
-        position: Box::new(move |color: Color| (color.chroma - start)/(end - start))
-    }
+pub fn luminance_lerp(space: WorkingSpace, start: f32, end: f32) -> Lerp {
+    axis_lerp(space, 0, start, end)
+}
+
+pub fn chroma_lerp(space: WorkingSpace, start: f32, end: f32) -> Lerp {
+    axis_lerp(space, 1, start, end)
+}
+
+pub fn hue_lerp(space: WorkingSpace, start: f32, end: f32) -> Lerp {
+    axis_lerp(space, 2, start, end)
 }
 
-pub fn hue_lerp(start: f32, end: f32) -> Lerp {
+pub fn alpha_lerp(start: f32, end: f32) -> Lerp {
     Lerp{
-        lerp: Box::new(move |color: Color, t| Color{hue: start + (end - start) * t, ..color}),
-        position: Box::new(move |color: Color| (color.hue - start)/(end - start))
+        lerp: Box::new(move |color: Color, t| Color{alpha: start + (end - start) * t, ..color}),
+        position: Box::new(move |color: Color| (color.alpha - start)/(end - start))
     }
 }
 
@@ -135,8 +381,10 @@ pub fn hue_lerp(start: f32, end: f32) -> Lerp {
 // list of shades, and position of current shade in that list
 pub fn shades(base_color: Color, max_shades: usize, lerp: &Lerp) -> (Vec<Color>, usize) {
     let width = 1.0 / max_shades as f32;
-    let index = ((lerp.position)(base_color) / width).floor() as usize;
-    let modulus = (lerp.position)(base_color) % width;
+    let position = (lerp.position)(base_color);
+    // the end of the axis is the last shade, not one past it
+    let index = ((position / width).floor() as usize).min(max_shades.saturating_sub(1));
+    let modulus = position - width * index as f32;
 
     ((0 .. max_shades)
         .map(|x| width * (x as f32) + modulus)
@@ -145,67 +393,349 @@ pub fn shades(base_color: Color, max_shades: usize, lerp: &Lerp) -> (Vec<Color>,
 }
 
 
-use serde_json::{Map, Value};
+// The dictionary a named color comes from. Among names of nearly the same color, the one from
+// the dictionary with the highest priority wins.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Source {
+    name: String,
+    priority: i32,
+}
+
+impl Source {
+    pub fn new(name: String, priority: i32) -> Self {
+        Source { name, priority }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn priority(&self) -> i32 {
+        self.priority
+    }
+}
 
+// A named color, measured with the metric `M` when stored in a `ColorDB`.
 #[derive(Debug, Clone)]
-pub struct NamedColor {
+pub struct NamedColor<M: ColorMetric = Ciede2000> {
     color: Color,
-    name: String
+    name: String,
+    source: Option<Arc<Source>>,
+    metric: PhantomData<M>,
 }   
 
-impl NamedColor {
+impl<M: ColorMetric> NamedColor<M> {
+    pub fn new(color: Color, name: String) -> Self {
+        NamedColor { color, name, source: None, metric: PhantomData }
+    }
+
+    pub fn with_source(self, source: Arc<Source>) -> Self {
+        NamedColor { source: Some(source), ..self }
+    }
+
     pub fn color(&self) -> Color {
         self.color
     }
     pub fn name(&self) -> &str {
         &self.name
     }   
+    pub fn source(&self) -> Option<&Source> {
+        self.source.as_deref()
+    }
+    pub fn priority(&self) -> i32 {
+        self.source().map_or(0, Source::priority)
+    }
 }
 
-impl MetricPoint for NamedColor {
+impl<M: ColorMetric> MetricPoint for NamedColor<M> {
     type Dist = f32;
 
     fn dist(from: &Self, to: &Self) -> Self::Dist {
-        MetricPoint::dist(&from.color, &to.color)
+        M::distance(&from.color, &to.color)
     }
 }
 
-impl PartialEq for NamedColor {
+impl<M: ColorMetric> PartialEq for NamedColor<M> {
     fn eq(&self, other: &Self) -> bool {
         self.name == other.name
     }
 }
 
-pub type ColorDB = VPTree<f32, NamedColor>;
+pub type ColorDB<M = Ciede2000> = VPTree<f32, NamedColor<M>>;
 pub struct Lerp {
     pub lerp: Box<dyn Fn(Color, f32) -> Color>,
     pub position: Box<dyn Fn(Color) -> f32>,
 }
 
-pub fn load_db(path: &Path) -> Result<ColorDB, io::Error> {
-    let data = fs::read_to_string(path)?;
-    let db: Map<String, Value> = serde_json::from_str(&data)?;
-    let db: Vec<NamedColor> = db.into_iter()
-        .map(|(entry, val)| {
-            if let Value::String(val) = val {
-                NamedColor{
-                    color: Color::from_hex(&entry),
-                    name: val
-                }
-            } else {
-                panic!("String values expected");
-            }
-        })
-        .collect();
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
 
+// Layers dictionaries into one database, see `quantize_color` for how priorities apply.
+pub fn build_db<M: ColorMetric>(dictionaries: &[Dictionary]) -> ColorDB<M> {
+    let db: Vec<NamedColor<M>> = dictionaries.iter().flat_map(Dictionary::named).collect();
     let vpt = VPTree::build(db);
-    println!("Database constructed. Height = {}.", vpt.height);
-    Ok(vpt)
+    log::info!("Database constructed. Height = {}.", vpt.height);
+    vpt
+}
+
+// Adds dictionaries to a built database, such as the bundled one.
+pub fn extend_db<M: ColorMetric>(db: &mut ColorDB<M>, dictionaries: &[Dictionary]) {
+    for named in dictionaries.iter().flat_map(Dictionary::named) {
+        db.insert(named);
+    }
+}
+
+// A database from a single dictionary file, see `Dictionary::load` for the formats.
+pub fn load_db<M: ColorMetric>(path: &Path) -> Result<ColorDB<M>, io::Error> {
+    Ok(build_db(&[Dictionary::load(path, 0)?]))
+}
+
+/* 
+ * A built database stored as is, so that loading it needs neither the JSON parse nor the tree 
+ * build. All numbers are little endian:
+ *
+ *      "BLDB" version:u8 metric:u8-length-prefixed-utf8 
+ *      sources:u16 sources * (name:u8-length-prefixed-utf8 priority:i32)
+ *      count:u32 count * (luminance:f32 chroma:f32 hue:f32 name:u16-length-prefixed-utf8
+ *                         source:u16 threshold:f32 near:u32 far:u32 removed:u8)
+ *
+ * Nodes are in preorder and children are node positions, `u32::MAX` when missing. A node's 
+ * source is a position in the sources, `u16::MAX` for none.
+ * */
+
+const DB_MAGIC: &[u8; 4] = b"BLDB";
+const DB_VERSION: u8 = 2;
+const DB_NO_CHILD: u32 = u32::MAX;
+const DB_NO_SOURCE: u16 = u16::MAX;
+
+// The bundled `res/colors.json`, built with the default metric by `blush-cli build-db`.
+pub const BUNDLED_DB: &[u8] = include_bytes!("../../res/colors.bin");
+
+// `len` as the integer it is written as, or an error if it doesn't fit.
+fn db_len<T: TryFrom<usize>>(len: usize, what: &str) -> Result<T, io::Error> {
+    T::try_from(len).map_err(|_| invalid(format!("{} is too long for a color database: {}", what, len)))
+}
+
+pub fn db_to_bytes<M: ColorMetric>(db: &ColorDB<M>) -> Result<Vec<u8>, io::Error> {
+    let flat = db.flatten();
+    let mut out = vec![];
+    out.extend_from_slice(DB_MAGIC);
+    out.push(DB_VERSION);
+    out.push(db_len(M::NAME.len(), "metric name")?);
+    out.extend_from_slice(M::NAME.as_bytes());
+
+    let mut sources: Vec<&Source> = vec![];
+    for node in &flat {
+        if let Some(source) = node.point.source() {
+            if !sources.contains(&source) {
+                sources.push(source);
+            }
+        }
+    }
+    // the last source id stands for none
+    let source_count: u16 = db_len(sources.len(), "source list")?;
+    if source_count == DB_NO_SOURCE {
+        return Err(invalid(format!("source list is too long for a color database: {}", sources.len())));
+    }
+    out.extend_from_slice(&source_count.to_le_bytes());
+    for source in &sources {
+        out.push(db_len(source.name.len(), "source name")?);
+        out.extend_from_slice(source.name.as_bytes());
+        out.extend_from_slice(&source.priority.to_le_bytes());
+    }
+
+    // the last node position stands for no child
+    let count: u32 = db_len(flat.len(), "node list")?;
+    if count == DB_NO_CHILD {
+        return Err(invalid(format!("node list is too long for a color database: {}", flat.len())));
+    }
+    out.extend_from_slice(&count.to_le_bytes());
+
+    let child = |child: Option<usize>| child.map_or(DB_NO_CHILD, |c| c as u32);
+    for node in flat {
+        let Color { luminance, chroma, hue, .. } = node.point.color;
+        for x in [luminance, chroma, hue] {
+            out.extend_from_slice(&x.to_le_bytes());
+        }
+        let name = node.point.name.as_bytes();
+        out.extend_from_slice(&db_len::<u16>(name.len(), "color name")?.to_le_bytes());
+        out.extend_from_slice(name);
+        let source = node.point.source()
+            .and_then(|source| sources.iter().position(|s| *s == source))
+            .map_or(DB_NO_SOURCE, |s| s as u16);
+        out.extend_from_slice(&source.to_le_bytes());
+        out.extend_from_slice(&node.threshold.to_le_bytes());
+        out.extend_from_slice(&child(node.near).to_le_bytes());
+        out.extend_from_slice(&child(node.far).to_le_bytes());
+        out.push(node.removed as u8);
+    }
+    Ok(out)
+}
+
+struct DbReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> DbReader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], io::Error> {
+        if self.data.len() < n {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated color database"));
+        }
+        let (head, tail) = self.data.split_at(n);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, io::Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, io::Error> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, io::Error> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32, io::Error> {
+        Ok(i32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> Result<f32, io::Error> {
+        Ok(f32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn str(&mut self, len: usize) -> Result<&'a str, io::Error> {
+        std::str::from_utf8(self.bytes(len)?).map_err(|_| invalid("invalid utf-8 name".to_owned()))
+    }
+
+    fn child(&mut self) -> Result<Option<usize>, io::Error> {
+        let child = self.u32()?;
+        Ok((child != DB_NO_CHILD).then_some(child as usize))
+    }
+}
+
+pub fn db_from_bytes<M: ColorMetric>(data: &[u8]) -> Result<ColorDB<M>, io::Error> {
+    let mut reader = DbReader { data };
+    if reader.bytes(4)? != DB_MAGIC {
+        return Err(invalid("not a color database".to_owned()));
+    }
+    let version = reader.u8()?;
+    if version != DB_VERSION {
+        return Err(invalid(format!("unsupported color database version {}", version)));
+    }
+    let len = reader.u8()? as usize;
+    let metric = reader.str(len)?;
+    if metric != M::NAME {
+        return Err(invalid(format!("color database was built for {}, not {}", metric, M::NAME)));
+    }
+
+    let mut sources = vec![];
+    for _ in 0..reader.u16()? {
+        let len = reader.u8()? as usize;
+        let name = reader.str(len)?.to_owned();
+        sources.push(Arc::new(Source::new(name, reader.i32()?)));
+    }
+
+    let count = reader.u32()? as usize;
+    // every node takes at least 29 bytes, don't trust the count any further
+    let mut flat = Vec::with_capacity(count.min(reader.data.len() / 29));
+    for _ in 0..count {
+        let color = Color { luminance: reader.f32()?, chroma: reader.f32()?, hue: reader.f32()?, alpha: 1.0 };
+        let len = reader.u16()? as usize;
+        let mut point = NamedColor::new(color, reader.str(len)?.to_owned());
+        match reader.u16()? {
+            DB_NO_SOURCE => (),
+            source => {
+                let source = sources.get(source as usize)
+                    .ok_or_else(|| invalid(format!("unknown source {}", source)))?;
+                point = point.with_source(source.clone());
+            }
+        }
+        flat.push(FlatNode {
+            point,
+            threshold: reader.f32()?,
+            near: reader.child()?,
+            far: reader.child()?,
+            removed: reader.u8()? != 0,
+        });
+    }
+    if !reader.data.is_empty() {
+        return Err(invalid("trailing bytes after color database".to_owned()));
+    }
+
+    VPTree::from_flat(flat)
+}
+
+pub fn bundled_db() -> ColorDB {
+    db_from_bytes(BUNDLED_DB).expect("the bundled color database is valid")
+}
+
+#[test]
+fn test_bundled_db() {
+    use rand::{Rng, SeedableRng};
+
+    // stale if res/colors.json or the tree build changed, rebuild with `blush-cli build-db`. The
+    // bytes themselves depend on the float functions of the platform, so what is compared is the
+    // colors held and the answers to queries.
+    let json: ColorDB = load_db(Path::new("res/colors.json")).unwrap();
+    let bundled = bundled_db();
+    assert_eq!(bundled.len(), json.len(), "res/colors.bin is out of date");
+
+    let entries = |db: &ColorDB| {
+        let mut entries: Vec<(String, Color)> = db.iter().map(|p| (p.name().to_owned(), p.color())).collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.hue.total_cmp(&b.1.hue)));
+        entries
+    };
+    for ((name, color), (json_name, json_color)) in entries(&bundled).into_iter().zip(entries(&json)) {
+        assert_eq!(name, json_name, "res/colors.bin is out of date");
+        let (a, b) = ([color.luminance, color.chroma, color.hue], [json_color.luminance, json_color.chroma, json_color.hue]);
+        assert!(a.iter().zip(b).all(|(x, y)| (x - y).abs() < 1e-5), "{} moved", name);
+    }
+
+    let mut rng = rand::rngs::SmallRng::seed_from_u64(0xdb);
+    for _ in 0..200 {
+        let probe = Color::from_rgb(rng.gen(), rng.gen(), rng.gen());
+        let (found, expected) = (quantize_color(&bundled, probe), quantize_color(&json, probe));
+        let dist = |p: &NamedColor| Ciede2000::distance(&p.color(), &probe);
+        assert!(found.name() == expected.name() || (dist(found) - dist(expected)).abs() < 1e-4,
+                "{} found {} instead of {}", probe, found.name(), expected.name());
+    }
+
+    let alice_blue = Color::from_hex("#f0f8ff");
+    assert_eq!(quantize_color(&bundled, alice_blue).name(), "Alice Blue");
+
+    assert!(db_from_bytes::<super::metric::Cie76>(BUNDLED_DB).is_err());
+    assert!(db_from_bytes::<Ciede2000>(&BUNDLED_DB[..BUNDLED_DB.len() - 1]).is_err());
+}
+
+#[test]
+fn test_db_lengths() {
+    let db: ColorDB = VPTree::build(vec![NamedColor::new(Color::default(), "x".repeat(u16::MAX as usize + 1))]);
+    assert!(db_to_bytes(&db).is_err());
+
+    let db: ColorDB = VPTree::build(vec![NamedColor::new(Color::default(), "x".repeat(u16::MAX as usize))]);
+    let back: ColorDB = db_from_bytes(&db_to_bytes(&db).unwrap()).unwrap();
+    assert_eq!(back.iter().next().unwrap().name().len(), u16::MAX as usize);
 }
 
-pub fn quantize_color(db: &ColorDB, color: Color) -> &NamedColor {
-    let named_color = NamedColor{ color, name: "".to_owned() };
-    db.nearest(&named_color)
+// Names from a higher priority dictionary win over closer ones that are less than this much
+// closer, about a just noticeable difference.
+const PRIORITY_TOLERANCE: f32 = 1.0;
+const PRIORITY_CANDIDATES: usize = 8;
+
+pub fn quantize_color<M: ColorMetric>(db: &ColorDB<M>, color: Color) -> &NamedColor<M> {
+    let named_color = NamedColor::new(color, "".to_owned());
+    let candidates = db.k_nearest(&named_color, PRIORITY_CANDIDATES);
+    let Some(&(_, closest)) = candidates.first() else {
+        return db.nearest(&named_color);
+    };
+    // candidates are closest first, and `min_by_key` keeps the first of equals
+    candidates.into_iter()
+        .take_while(|(_, dist)| *dist <= closest + PRIORITY_TOLERANCE)
+        .min_by_key(|(named, _)| Reverse(named.priority()))
+        .unwrap().0
 }
 
 
@@ -0,0 +1,254 @@
+/*
+ * Named color dictionaries, to be layered into a `ColorDB` with `color::build_db`. Every
+ * dictionary is one source with a priority; its names are attributed to it in results.
+ *
+ * Dictionaries are read from
+ *      .json   {"#rrggbb": "name", ...}
+ *      .csv    one `name,color` (or `color,name`) per line, an optional header first; colors
+ *              are in any CSS color syntax
+ *      .gpl    a GIMP palette, using its named colors
+ * */
+
+use std::{fs, io, path::Path, sync::Arc};
+
+use serde_json::{Map, Value};
+
+use crate::formats::gpl;
+
+use super::{color::{Color, NamedColor, Source}, css_colors::{CSS_COLORS, css_color}, metric::ColorMetric};
+
+#[derive(Debug, Clone)]
+pub struct Dictionary {
+    source: Arc<Source>,
+    entries: Vec<(Color, String)>,
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// Hex digits without a `#`, which parse as a color but may well be a name such as "Cafe".
+fn bare_hex(field: &str) -> bool {
+    field.chars().all(|ch| ch.is_ascii_hexdigit())
+}
+
+// The color and the name of a `name,color` or `color,name` line. A name that is a CSS keyword or
+// only hex digits parses as a color too, the other field is the color then; if both are only hex
+// digits, the color needs its `#`.
+fn csv_entry<'a>(a: &'a str, b: &'a str) -> Result<(Color, &'a str), String> {
+    match (a.parse::<Color>(), b.parse::<Color>()) {
+        (Ok(color), Err(_)) => Ok((color, b)),
+        (Err(_), Ok(color)) => Ok((color, a)),
+        (Ok(_), Ok(_)) if bare_hex(a) && bare_hex(b) =>
+            Err("expected a name and a color, write the color with its #".to_owned()),
+        (Ok(_), Ok(color)) if css_color(a).is_some() || bare_hex(a) => Ok((color, a)),
+        (Ok(color), Ok(_)) if css_color(b).is_some() || bare_hex(b) => Ok((color, b)),
+        (Ok(_), Ok(_)) => Err("expected a name and a color, found two colors".to_owned()),
+        (Err(_), Err(err)) => Err(err.to_string()),
+    }
+}
+
+fn is_blank(ch: &char) -> bool {
+    *ch == ' ' || *ch == '\t'
+}
+
+// The fields of a CSV line, trimmed. Quoted fields may hold commas, and `""` for a quote.
+fn csv_fields(line: &str) -> Result<Vec<String>, String> {
+    let mut fields = vec![];
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(is_blank).is_some() {}
+        let mut field = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            loop {
+                match chars.next() {
+                    Some('"') if chars.next_if_eq(&'"').is_some() => field.push('"'),
+                    Some('"') => break,
+                    Some(ch) => field.push(ch),
+                    None => return Err("unterminated quote".to_owned()),
+                }
+            }
+            while chars.next_if(is_blank).is_some() {}
+            if chars.peek().is_some_and(|ch| *ch != ',') {
+                return Err("expected a comma after a quoted field".to_owned());
+            }
+        } else {
+            while let Some(ch) = chars.next_if(|ch| *ch != ',') {
+                field.push(ch);
+            }
+            field.truncate(field.trim_end().len());
+        }
+        fields.push(field);
+        if chars.next().is_none() {
+            return Ok(fields);
+        }
+    }
+}
+
+impl Dictionary {
+    pub fn new(source: Source, entries: Vec<(Color, String)>) -> Self {
+        Dictionary { source: Arc::new(source), entries }
+    }
+
+    pub fn source(&self) -> &Source {
+        &self.source
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn named<M: ColorMetric>(&self) -> impl Iterator<Item = NamedColor<M>> + '_ {
+        self.entries.iter()
+            .map(|(color, name)| NamedColor::new(*color, name.clone()).with_source(self.source.clone()))
+    }
+
+    // The CSS named colors, by keyword.
+    pub fn css(priority: i32) -> Self {
+        let entries = CSS_COLORS.iter()
+            .map(|(name, hex)| (Color::from_hex(hex), (*name).to_owned()))
+            .collect();
+        Dictionary::new(Source::new("css".to_owned(), priority), entries)
+    }
+
+    pub fn from_json(source: Source, data: &str) -> Result<Self, io::Error> {
+        let db: Map<String, Value> = serde_json::from_str(data)?;
+        let entries = db.into_iter()
+            .map(|(entry, val)| {
+                if !entry.starts_with('#') {
+                    return Err(invalid(format!("expected a hex color: {}", entry)));
+                }
+                let color: Color = entry.parse().map_err(|err| invalid(format!("{}: {}", entry, err)))?;
+                match val {
+                    Value::String(val) => Ok((color, val)),
+                    _ => Err(invalid(format!("expected a name for {}", entry))),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Dictionary::new(source, entries))
+    }
+
+    pub fn from_csv(source: Source, data: &str) -> Result<Self, io::Error> {
+        let mut entries = vec![];
+        for (idx, line) in data.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry = csv_fields(line).and_then(|fields| match &fields[..] {
+                [a, b] => csv_entry(a, b).map(|(color, name)| (color, name.to_owned())),
+                _ => Err("expected a name and a color".to_owned()),
+            });
+            match entry {
+                Ok(entry) => entries.push(entry),
+                // the header
+                Err(_) if entries.is_empty() && idx == 0 => (),
+                Err(err) => return Err(invalid(format!("line {}: {}", idx + 1, err))),
+            }
+        }
+        Ok(Dictionary::new(source, entries))
+    }
+
+    // Unnamed colors of the palette are left out.
+    pub fn from_gpl(source: Source, data: &str) -> Result<Self, io::Error> {
+        let map = gpl::from_str(data)?;
+        let entries = map.cells().into_iter()
+            .filter_map(|(_, index, color)| Some((color, map.name_of(index)?.to_owned())))
+            .collect();
+        Ok(Dictionary::new(source, entries))
+    }
+
+    // Picks the format by extension; the source is named after the file.
+    pub fn load(path: &Path, priority: i32) -> Result<Self, io::Error> {
+        let data = fs::read_to_string(path)?;
+        let name = path.file_name().map_or(String::new(), |name| name.to_string_lossy().into_owned());
+        let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or_default().to_lowercase();
+        let source = Source::new(name, priority);
+
+        match extension.as_str() {
+            "json" => Dictionary::from_json(source, &data),
+            "csv" => Dictionary::from_csv(source, &data),
+            "gpl" => Dictionary::from_gpl(source, &data),
+            _ => Err(io::Error::new(io::ErrorKind::Unsupported,
+                                    format!("unknown dictionary format: {}", path.display()))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod dictionary_tests {
+    use super::{Dictionary, csv_fields};
+    use crate::util::{color::{Color, ColorDB, Source, build_db, quantize_color}, metric::Ciede2000};
+
+    fn source(priority: i32) -> Source {
+        Source::new("test".to_owned(), priority)
+    }
+
+    #[test]
+    fn json_rejects_malformed() {
+        assert!(Dictionary::from_json(source(0), r##"{"#f0f8ff": 3}"##).is_err());
+        assert!(Dictionary::from_json(source(0), r##"{"f0f8ff": "Alice Blue"}"##).is_err());
+        assert!(Dictionary::from_json(source(0), r##"{"#f0f8fg": "Alice Blue"}"##).is_err());
+        assert_eq!(Dictionary::from_json(source(0), r##"{"#f0f8ff": "Alice Blue"}"##).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn csv_entries() {
+        let csv = "name,hex\n\"Brand Red\",#e03030\n1f2a44, Brand Navy\n\n";
+        let dict = Dictionary::from_csv(source(0), csv).unwrap();
+        assert_eq!(dict.len(), 2);
+        assert!(Dictionary::from_csv(source(0), "Brand Red,#e03030\nnot a color\n").is_err());
+        assert!(Dictionary::from_csv(source(0), "Brand Red,#e03030\nBrand Blue,#e0303z\n").is_err());
+
+        let keyword = Dictionary::from_csv(source(0), "red,#e03030\nrgb(0 0 255),Blue Brand\n").unwrap();
+        let entries: Vec<(String, String)> = keyword.named::<Ciede2000>()
+            .map(|named| (named.name().to_owned(), named.color().to_hex()))
+            .collect();
+        assert_eq!(entries, [("red".to_owned(), "#e03030".to_owned()), ("Blue Brand".to_owned(), "#0000ff".to_owned())]);
+    }
+
+    #[test]
+    fn csv_quoting() {
+        assert_eq!(csv_fields(r#" "Red, Dark" , "rgb(139, 0, 0)""#).unwrap(), ["Red, Dark", "rgb(139, 0, 0)"]);
+        assert_eq!(csv_fields(r#""Say ""Cheese""",#ffd700"#).unwrap(), [r#"Say "Cheese""#, "#ffd700"]);
+        assert_eq!(csv_fields("a,,b").unwrap(), ["a", "", "b"]);
+        assert!(csv_fields(r#""Red,#ff0000"#).is_err());
+        assert!(csv_fields(r#""Red" x,#ff0000"#).is_err());
+
+        let dict = Dictionary::from_csv(source(0), "\"Red, Dark\",\"rgb(139, 0, 0)\"\n").unwrap();
+        let named: Vec<_> = dict.named::<Ciede2000>().collect();
+        assert_eq!((named[0].name(), named[0].color().to_hex().as_str()), ("Red, Dark", "#8b0000"));
+    }
+
+    // Names made of hex digits are names next to a color, but two of them are ambiguous.
+    #[test]
+    fn csv_hex_names() {
+        let dict = Dictionary::from_csv(source(0), "Cafe,#c0ffee\n#bad,Bad\nFade,rgb(1 2 3)\n").unwrap();
+        let names: Vec<String> = dict.named::<Ciede2000>().map(|named| named.name().to_owned()).collect();
+        assert_eq!(names, ["Cafe", "Bad", "Fade"]);
+        assert!(Dictionary::from_csv(source(0), "Cafe,#c0ffee\nBeef,c0ffee\n").is_err());
+    }
+
+    #[test]
+    fn gpl_entries() {
+        let gpl = "GIMP Palette\nName: brand\n#\n224  48  48\tBrand Red\n 31  42  68\n";
+        let dict = Dictionary::from_gpl(source(0), gpl).unwrap();
+        assert_eq!(dict.len(), 1);
+    }
+
+    // A brand name one step away from a CSS color is preferred over it.
+    #[test]
+    fn priority_wins_near_ties() {
+        let brand = Dictionary::from_csv(source(1), "Brand Red,#fe0000").unwrap();
+        let db: ColorDB = build_db(&[Dictionary::css(0), brand]);
+        let named = quantize_color(&db, Color::from_hex("#ff0000"));
+        assert_eq!(named.name(), "Brand Red");
+        assert_eq!(named.source().unwrap().name(), "test");
+
+        let far = quantize_color(&db, Color::from_hex("#0000ff"));
+        assert_eq!((far.name(), far.source().unwrap().name()), ("blue", "css"));
+    }
+}
@@ -0,0 +1,85 @@
+/* 
+ * A value computed without blocking the UI: on a background thread natively, and on the web, 
+ * where there are no threads, during the second frame so that the first one shows up quickly.
+ * `poll` once per frame, then `get` the value once it is there.
+ * */
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::{sync::mpsc::{self, Receiver, TryRecvError}, thread};
+
+enum Pending<T> {
+    #[cfg(not(target_arch = "wasm32"))]
+    Thread(Receiver<T>),
+    #[cfg(target_arch = "wasm32")]
+    Deferred { load: Box<dyn FnOnce() -> T>, polled: bool },
+    Ready(T),
+    // the loading thread panicked
+    Failed,
+}
+
+pub struct Loader<T> {
+    pending: Pending<T>,
+}
+
+impl<T: Send + 'static> Loader<T> {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn spawn(load: impl FnOnce() -> T + Send + 'static) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = sender.send(load());
+        });
+        Loader { pending: Pending::Thread(receiver) }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn spawn(load: impl FnOnce() -> T + Send + 'static) -> Self {
+        Loader { pending: Pending::Deferred { load: Box::new(load), polled: false } }
+    }
+
+    pub fn poll(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Pending::Thread(receiver) = &self.pending {
+            self.pending = match receiver.try_recv() {
+                Ok(value) => Pending::Ready(value),
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => Pending::Failed,
+            };
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        if let Pending::Deferred { polled, .. } = &mut self.pending {
+            if !*polled {
+                *polled = true;
+                return;
+            }
+            if let Pending::Deferred { load, .. } = std::mem::replace(&mut self.pending, Pending::Failed) {
+                self.pending = Pending::Ready(load());
+            }
+        }
+    }
+
+    pub fn get(&self) -> Option<&T> {
+        match &self.pending {
+            Pending::Ready(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod loader_tests {
+    use super::Loader;
+
+    #[test]
+    fn loads_in_background() {
+        let mut loader = Loader::spawn(|| 6 * 7);
+        for _ in 0..1000 {
+            loader.poll();
+            if loader.get().is_some() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        assert_eq!(loader.get(), Some(&42));
+    }
+}
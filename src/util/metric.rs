@@ -0,0 +1,249 @@
+/* 
+ * Perceptual color differences, computed on CIE Lab coordinates recovered from the normalized
+ * LCH of `Color`. Every metric is a marker type, so the metric used by a `VPTree` is picked
+ * through the point type: `NamedColor<M>` for the named color database, `Measured<M>` for
+ * plain colors.
+ *
+ * CIEDE2000 is not a true metric (the triangle inequality can fail by small amounts), so tree
+ * searches using it may very rarely miss the exact nearest point; the miss is always a near tie.
+ * */
+
+use std::{marker::PhantomData, f64::consts::PI};
+
+use super::{color::Color, vptree::MetricPoint};
+
+pub trait ColorMetric {
+    // Identifies the metric a cached `ColorDB` was built with.
+    const NAME: &'static str;
+    fn distance(from: &Color, to: &Color) -> f32;
+}
+
+// Euclidean distance in Lab.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Cie76;
+
+// CIE94 with graphic arts weights. The chroma weighting uses the geometric mean of both chromas
+// instead of the reference chroma, so that the distance is symmetric.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Cie94;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Ciede2000;
+
+pub(crate) type Lab = [f64; 3];
+
+pub(crate) fn lab(color: &Color) -> Lab {
+    let l = color.luminance as f64 * 100.0;
+    let c = color.chroma as f64 * 100.0;
+    let h = color.hue as f64 * 2.0 * PI;
+    [l, c * h.cos(), c * h.sin()]
+}
+
+// An opaque color back from its Lab coordinates.
+pub(crate) fn from_lab([l, a, b]: Lab) -> Color {
+    Color {
+        luminance: (l / 100.0) as f32,
+        chroma: (a.hypot(b) / 100.0) as f32,
+        hue: (b.atan2(a) / (2.0 * PI)).rem_euclid(1.0) as f32,
+        alpha: 1.0,
+    }
+}
+
+fn cie76(from: Lab, to: Lab) -> f64 {
+    let [l1, a1, b1] = from;
+    let [l2, a2, b2] = to;
+    ((l2 - l1).powi(2) + (a2 - a1).powi(2) + (b2 - b1).powi(2)).sqrt()
+}
+
+fn cie94(from: Lab, to: Lab) -> f64 {
+    const K1: f64 = 0.045;
+    const K2: f64 = 0.015;
+
+    let [l1, a1, b1] = from;
+    let [l2, a2, b2] = to;
+    let c1 = a1.hypot(b1);
+    let c2 = a2.hypot(b2);
+
+    let dl = l2 - l1;
+    let dc = c2 - c1;
+    let dh2 = ((a2 - a1).powi(2) + (b2 - b1).powi(2) - dc * dc).max(0.0);
+
+    let c = (c1 * c2).sqrt();
+    let sc = 1.0 + K1 * c;
+    let sh = 1.0 + K2 * c;
+
+    (dl * dl + (dc / sc).powi(2) + dh2 / (sh * sh)).sqrt()
+}
+
+// Sharma, Wu and Dalal, "The CIEDE2000 color-difference formula: implementation notes,
+// supplementary test data, and mathematical observations" (2005).
+fn ciede2000(from: Lab, to: Lab) -> f64 {
+    let [l1, a1, b1] = from;
+    let [l2, a2, b2] = to;
+
+    let pow25_7 = 25f64.powi(7);
+    let c_bar = (a1.hypot(b1) + a2.hypot(b2)) / 2.0;
+    let g = 0.5 * (1.0 - (c_bar.powi(7) / (c_bar.powi(7) + pow25_7)).sqrt());
+
+    let a1p = (1.0 + g) * a1;
+    let a2p = (1.0 + g) * a2;
+    let c1p = a1p.hypot(b1);
+    let c2p = a2p.hypot(b2);
+
+    let hue = |b: f64, a: f64| if a == 0.0 && b == 0.0 { 0.0 } else { b.atan2(a).to_degrees().rem_euclid(360.0) };
+    let h1p = hue(b1, a1p);
+    let h2p = hue(b2, a2p);
+
+    let dlp = l2 - l1;
+    let dcp = c2p - c1p;
+
+    let chroma_zero = c1p * c2p == 0.0;
+    let dhp = if chroma_zero {
+        0.0
+    } else if (h2p - h1p).abs() <= 180.0 {
+        h2p - h1p
+    } else if h2p - h1p > 180.0 {
+        h2p - h1p - 360.0
+    } else {
+        h2p - h1p + 360.0
+    };
+    let dhp_big = 2.0 * (c1p * c2p).sqrt() * (dhp / 2.0).to_radians().sin();
+
+    let l_bar = (l1 + l2) / 2.0;
+    let c_bar_p = (c1p + c2p) / 2.0;
+    let h_bar_p = if chroma_zero {
+        h1p + h2p
+    } else if (h1p - h2p).abs() <= 180.0 {
+        (h1p + h2p) / 2.0
+    } else if h1p + h2p < 360.0 {
+        (h1p + h2p + 360.0) / 2.0
+    } else {
+        (h1p + h2p - 360.0) / 2.0
+    };
+
+    let cos_deg = |x: f64| x.to_radians().cos();
+    let t = 1.0 - 0.17 * cos_deg(h_bar_p - 30.0) + 0.24 * cos_deg(2.0 * h_bar_p)
+        + 0.32 * cos_deg(3.0 * h_bar_p + 6.0) - 0.20 * cos_deg(4.0 * h_bar_p - 63.0);
+    let d_theta = 30.0 * (-((h_bar_p - 275.0) / 25.0).powi(2)).exp();
+    let rc = 2.0 * (c_bar_p.powi(7) / (c_bar_p.powi(7) + pow25_7)).sqrt();
+    let sl = 1.0 + 0.015 * (l_bar - 50.0).powi(2) / (20.0 + (l_bar - 50.0).powi(2)).sqrt();
+    let sc = 1.0 + 0.045 * c_bar_p;
+    let sh = 1.0 + 0.015 * c_bar_p * t;
+    let rt = -(2.0 * d_theta).to_radians().sin() * rc;
+
+    let (l, c, h) = (dlp / sl, dcp / sc, dhp_big / sh);
+    (l * l + c * c + h * h + rt * c * h).max(0.0).sqrt()
+}
+
+impl ColorMetric for Cie76 {
+    const NAME: &'static str = "cie76";
+
+    fn distance(from: &Color, to: &Color) -> f32 {
+        cie76(lab(from), lab(to)) as f32
+    }
+}
+
+impl ColorMetric for Cie94 {
+    const NAME: &'static str = "cie94";
+
+    fn distance(from: &Color, to: &Color) -> f32 {
+        cie94(lab(from), lab(to)) as f32
+    }
+}
+
+impl ColorMetric for Ciede2000 {
+    const NAME: &'static str = "ciede2000";
+
+    fn distance(from: &Color, to: &Color) -> f32 {
+        ciede2000(lab(from), lab(to)) as f32
+    }
+}
+
+// A plain color measured with the metric `M`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Measured<M: ColorMetric> {
+    pub color: Color,
+    metric: PhantomData<M>,
+}
+
+impl<M: ColorMetric> Measured<M> {
+    pub fn new(color: Color) -> Self {
+        Measured { color, metric: PhantomData }
+    }
+}
+
+impl<M: ColorMetric> MetricPoint for Measured<M> {
+    type Dist = f32;
+
+    fn dist(from: &Self, to: &Self) -> Self::Dist {
+        M::distance(&from.color, &to.color)
+    }
+}
+
+#[cfg(test)]
+mod metric_tests {
+    use super::{ciede2000, cie76, cie94, ColorMetric, Ciede2000};
+    use crate::util::color::Color;
+
+    // (L1, a1, b1, L2, a2, b2, dE00) from Sharma et al.
+    const SHARMA: [[f64; 7]; 34] = [
+        [50.0000, 2.6772, -79.7751, 50.0000, 0.0000, -82.7485, 2.0425],
+        [50.0000, 3.1571, -77.2803, 50.0000, 0.0000, -82.7485, 2.8615],
+        [50.0000, 2.8361, -74.0200, 50.0000, 0.0000, -82.7485, 3.4412],
+        [50.0000, -1.3802, -84.2814, 50.0000, 0.0000, -82.7485, 1.0000],
+        [50.0000, -1.1848, -84.8006, 50.0000, 0.0000, -82.7485, 1.0000],
+        [50.0000, -0.9009, -85.5211, 50.0000, 0.0000, -82.7485, 1.0000],
+        [50.0000, 0.0000, 0.0000, 50.0000, -1.0000, 2.0000, 2.3669],
+        [50.0000, -1.0000, 2.0000, 50.0000, 0.0000, 0.0000, 2.3669],
+        [50.0000, 2.4900, -0.0010, 50.0000, -2.4900, 0.0009, 7.1792],
+        [50.0000, 2.4900, -0.0010, 50.0000, -2.4900, 0.0010, 7.1792],
+        [50.0000, 2.4900, -0.0010, 50.0000, -2.4900, 0.0011, 7.2195],
+        [50.0000, 2.4900, -0.0010, 50.0000, -2.4900, 0.0012, 7.2195],
+        [50.0000, -0.0010, 2.4900, 50.0000, 0.0009, -2.4900, 4.8045],
+        [50.0000, -0.0010, 2.4900, 50.0000, 0.0010, -2.4900, 4.8045],
+        [50.0000, -0.0010, 2.4900, 50.0000, 0.0011, -2.4900, 4.7461],
+        [50.0000, 2.5000, 0.0000, 50.0000, 0.0000, -2.5000, 4.3065],
+        [50.0000, 2.5000, 0.0000, 73.0000, 25.0000, -18.0000, 27.1492],
+        [50.0000, 2.5000, 0.0000, 61.0000, -5.0000, 29.0000, 22.8977],
+        [50.0000, 2.5000, 0.0000, 56.0000, -27.0000, -3.0000, 31.9030],
+        [50.0000, 2.5000, 0.0000, 58.0000, 24.0000, 15.0000, 19.4535],
+        [50.0000, 2.5000, 0.0000, 50.0000, 3.1736, 0.5854, 1.0000],
+        [50.0000, 2.5000, 0.0000, 50.0000, 3.2972, 0.0000, 1.0000],
+        [50.0000, 2.5000, 0.0000, 50.0000, 1.8634, 0.5757, 1.0000],
+        [50.0000, 2.5000, 0.0000, 50.0000, 3.2592, 0.3350, 1.0000],
+        [60.2574, -34.0099, 36.2677, 60.4626, -34.1751, 39.4387, 1.2644],
+        [63.0109, -31.0961, -5.8663, 62.8187, -29.7946, -4.0864, 1.2630],
+        [61.2901, 3.7196, -5.3901, 61.4292, 2.2480, -4.9620, 1.8731],
+        [35.0831, -44.1164, 3.7933, 35.0232, -40.0716, 1.5901, 1.8645],
+        [22.7233, 20.0904, -46.6940, 23.0331, 14.9730, -42.5619, 2.0373],
+        [36.4612, 47.8580, 18.3852, 36.2715, 50.5065, 21.2231, 1.4146],
+        [90.8027, -2.0831, 1.4410, 91.1528, -1.6435, 0.0447, 1.4441],
+        [90.9257, -0.5406, -0.9208, 88.6381, -0.8985, -0.7239, 1.5381],
+        [6.7747, -0.2908, -2.4247, 5.8714, -0.0985, -2.2286, 0.6377],
+        [2.0776, 0.0795, -1.1350, 0.9033, -0.0636, -0.5514, 0.9082],
+    ];
+
+    #[test]
+    fn ciede2000_reference_pairs() {
+        for [l1, a1, b1, l2, a2, b2, expected] in SHARMA {
+            let forward = ciede2000([l1, a1, b1], [l2, a2, b2]);
+            let backward = ciede2000([l2, a2, b2], [l1, a1, b1]);
+            assert!((forward - expected).abs() < 1e-4, "{} != {}", forward, expected);
+            assert!((backward - expected).abs() < 1e-4, "{} != {}", backward, expected);
+        }
+    }
+
+    #[test]
+    fn simpler_metrics() {
+        assert!((cie76([50.0, 0.0, 0.0], [53.0, 4.0, 0.0]) - 5.0).abs() < 1e-9);
+        assert!((cie94([50.0, 0.0, 0.0], [53.0, 0.0, 0.0]) - 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn color_distance_is_symmetric() {
+        let a = Color::from_hex("#0e5d83");
+        let b = Color::from_hex("#de5d83");
+        assert_eq!(Ciede2000::distance(&a, &a), 0.0);
+        assert_eq!(Ciede2000::distance(&a, &b), Ciede2000::distance(&b, &a));
+    }
+}
@@ -1,7 +1,16 @@
 pub mod vptree;
 pub mod color;
+pub mod metric;
 pub mod buffer;
 pub mod rounding_util;
+pub mod loader;
+pub mod name_index;
+pub mod dictionary;
+pub mod css_colors;
+pub mod vision;
+pub mod space;
+pub mod notation;
 
 pub use rounding_util::RoundedRect;
 pub use rounding_util::RoundingLegend;
+pub use rounding_util::{tesselate, CHECKER_COLORS};
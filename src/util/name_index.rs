@@ -0,0 +1,149 @@
+/*
+ * Looks up named colors by (part of) their name. Matching ignores case: names starting with the
+ * query come first, then names with a word starting with it, then names within a few typos of
+ * it, counted as the edit distance to the whole name or to its closest word. Equally good 
+ * matches from higher priority dictionaries come first.
+ * */
+
+use std::cmp::Ordering;
+
+use super::color::{Color, ColorDB};
+
+struct Entry {
+    key: String,
+    name: String,
+    source: String,
+    priority: i32,
+    color: Color,
+}
+
+pub struct NameMatch<'a> {
+    pub name: &'a str,
+    pub source: &'a str,
+    pub color: Color,
+}
+
+pub struct NameIndex {
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Rank {
+    Prefix,
+    WordPrefix,
+    Fuzzy(usize),
+}
+
+// Levenshtein distance, over chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diag = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let next = (diag + usize::from(ca != *cb)).min(row[j] + 1).min(row[j + 1] + 1);
+            diag = row[j + 1];
+            row[j + 1] = next;
+        }
+    }
+    row[b.len()]
+}
+
+// Typos tolerated in a query, one per three letters.
+fn max_typos(query: &str) -> usize {
+    query.chars().count() / 3
+}
+
+impl NameIndex {
+    pub fn new(db: &ColorDB) -> Self {
+        let entries = db.iter()
+            .map(|named| Entry {
+                key: named.name().to_lowercase(),
+                name: named.name().to_owned(),
+                source: named.source().map_or(String::new(), |source| source.name().to_owned()),
+                priority: named.priority(),
+                color: named.color(),
+            })
+            .collect();
+        NameIndex { entries }
+    }
+
+    fn rank(entry: &Entry, query: &str) -> Option<Rank> {
+        if entry.key.starts_with(query) {
+            return Some(Rank::Prefix);
+        }
+        let mut words = entry.key.split_whitespace();
+        if words.any(|word| word.starts_with(query)) {
+            return Some(Rank::WordPrefix);
+        }
+        let typos = entry.key.split_whitespace()
+            .map(|word| edit_distance(query, word))
+            .chain([edit_distance(query, &entry.key)])
+            .min()?;
+        (typos <= max_typos(query)).then_some(Rank::Fuzzy(typos))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // The best `limit` matches for `query`, best first.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<NameMatch<'_>> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return vec![];
+        }
+
+        let mut found: Vec<(Rank, &Entry)> = self.entries.iter()
+            .filter_map(|entry| Some((Self::rank(entry, &query)?, entry)))
+            .collect();
+        // shorter names are closer to what was typed
+        found.sort_by(|(ra, a), (rb, b)| match ra.cmp(rb) {
+            Ordering::Equal => b.priority.cmp(&a.priority)
+                .then_with(|| a.key.len().cmp(&b.key.len()))
+                .then_with(|| a.key.cmp(&b.key)),
+            order => order,
+        });
+        found.into_iter()
+            .take(limit)
+            .map(|(_, entry)| NameMatch { name: &entry.name, source: &entry.source, color: entry.color })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod name_index_tests {
+    use std::path::Path;
+
+    use super::{NameIndex, edit_distance};
+    use crate::util::color::load_db;
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "blue"), 4);
+        assert_eq!(edit_distance("blue", "blue"), 0);
+    }
+
+    #[test]
+    fn search_names() {
+        let index = NameIndex::new(&load_db(Path::new("res/colors.json")).unwrap());
+        let names = |query: &str| -> Vec<String> {
+            index.search(query, 5).into_iter().map(|found| found.name.to_owned()).collect()
+        };
+
+        assert_eq!(names("alice")[0], "Alice Blue");
+        assert_eq!(names("  ACID GR")[0], "Acid Green");
+        // a word inside the name
+        assert!(names("blue").contains(&"Blue".to_owned()));
+        // typos
+        assert_eq!(names("alcie blue")[0], "Alice Blue");
+        assert!(names("").is_empty());
+        assert!(names("qqqqqq").is_empty());
+    }
+}
@@ -0,0 +1,406 @@
+/*
+ * CSS color syntax: `Color` parses from and displays in
+ *      #rgb #rgba #rrggbb #rrggbbaa (the '#' may be left out)
+ *      rgb() rgba() hsl() hsla() hwb() lab() lch() oklch()
+ *      the CSS named colors
+ * Functions take both the comma separated legacy syntax and the space separated one, with the
+ * alpha after a '/'; every argument may be `none`. Translucent colors show their alpha the same way.
+ *
+ * rgb, hsl, hwb and hex show the gamut mapped color, lab, lch and oklch show it as it is.
+ * */
+
+use std::{fmt, str::FromStr};
+
+use palette::{convert::FromColorUnclamped, Hsl, Hwb, Lab, Lch, Oklch, Srgb};
+
+use super::{color::Color, css_colors::css_color, space};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ColorParseError {
+    Empty,
+    // a '#' color that is not 3, 4, 6 or 8 hex digits
+    Hex(String),
+    UnknownName(String),
+    UnknownFunction(String),
+    // a function without its closing parenthesis, or a misplaced '/'
+    Syntax(String),
+    Arguments { function: String, expected: usize, found: usize },
+    Value { function: String, value: String },
+}
+
+impl fmt::Display for ColorParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColorParseError::Empty => write!(f, "empty color"),
+            ColorParseError::Hex(hex) => write!(f, "invalid hex color: {}", hex),
+            ColorParseError::UnknownName(name) => write!(f, "unknown color name: {}", name),
+            ColorParseError::UnknownFunction(function) => write!(f, "unknown color function: {}()", function),
+            ColorParseError::Syntax(color) => write!(f, "malformed color: {}", color),
+            ColorParseError::Arguments { function, expected, found } =>
+                write!(f, "{}() takes {} values, found {}", function, expected, found),
+            ColorParseError::Value { function, value } => write!(f, "invalid value in {}(): {}", function, value),
+        }
+    }
+}
+
+impl std::error::Error for ColorParseError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Notation {
+    Hex,
+    Rgb,
+    Hsl,
+    Hwb,
+    Lab,
+    Lch,
+    Oklch,
+}
+
+impl Notation {
+    pub const ALL: [Notation; 7] = [Notation::Hex, Notation::Rgb, Notation::Hsl, Notation::Hwb,
+                                    Notation::Lab, Notation::Lch, Notation::Oklch];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Notation::Hex => "hex",
+            Notation::Rgb => "rgb",
+            Notation::Hsl => "hsl",
+            Notation::Hwb => "hwb",
+            Notation::Lab => "lab",
+            Notation::Lch => "lch",
+            Notation::Oklch => "oklch",
+        }
+    }
+}
+
+// A color shown in one notation, see `Color::display`.
+pub struct Notated {
+    color: Color,
+    notation: Notation,
+}
+
+// A function argument.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Value {
+    Number(f32),
+    Percent(f32),
+    // in degrees
+    Angle(f32),
+    None,
+}
+
+fn value(token: &str) -> Option<Value> {
+    if token.eq_ignore_ascii_case("none") {
+        return Some(Value::None);
+    }
+    if let Some(percent) = token.strip_suffix('%') {
+        return percent.parse().ok().map(Value::Percent);
+    }
+    let token = token.to_ascii_lowercase();
+    for (unit, degrees) in [("deg", 1.0), ("grad", 0.9), ("rad", 180.0 / std::f32::consts::PI), ("turn", 360.0)] {
+        if let Some(angle) = token.strip_suffix(unit) {
+            return angle.parse::<f32>().ok().map(|angle| Value::Angle(angle * degrees));
+        }
+    }
+    token.parse().ok().map(Value::Number)
+}
+
+// The arguments of one function, read left to right.
+struct Arguments<'a> {
+    function: &'a str,
+    values: Vec<Value>,
+    alpha: Option<Value>,
+}
+
+impl<'a> Arguments<'a> {
+    fn parse(function: &'a str, text: &str) -> Result<Self, ColorParseError> {
+        let invalid = |token: &str| ColorParseError::Value { function: function.to_owned(), value: token.to_owned() };
+        let parse_all = |tokens: &[&str]| tokens.iter().map(|token| value(token).ok_or_else(|| invalid(token)))
+            .collect::<Result<Vec<Value>, ColorParseError>>();
+
+        let (mut values, alpha) = if text.contains(',') {
+            let mut values = parse_all(&text.split(',').map(str::trim).collect::<Vec<&str>>())?;
+            // rgba(r, g, b, a)
+            let alpha = (values.len() == 4).then(|| values.pop()).flatten();
+            (values, alpha)
+        } else {
+            let spaced = text.replace('/', " / ");
+            let tokens: Vec<&str> = spaced.split_whitespace().collect();
+            match tokens.iter().position(|token| *token == "/") {
+                Some(slash) if slash + 2 == tokens.len() => (parse_all(&tokens[..slash])?, Some(parse_all(&tokens[slash + 1..])?[0])),
+                Some(_) => return Err(ColorParseError::Syntax(format!("{}({})", function, text))),
+                None => (parse_all(&tokens)?, None),
+            }
+        };
+        if values.len() != 3 {
+            return Err(ColorParseError::Arguments { function: function.to_owned(), expected: 3, found: values.len() });
+        }
+        values.reverse();
+        Ok(Arguments { function, values, alpha })
+    }
+
+    fn invalid(&self, value: Value) -> ColorParseError {
+        let value = match value {
+            Value::Number(x) => x.to_string(),
+            Value::Percent(x) => format!("{}%", x),
+            Value::Angle(x) => format!("{}deg", x),
+            Value::None => "none".to_owned(),
+        };
+        ColorParseError::Value { function: self.function.to_owned(), value }
+    }
+
+    // A number, or a percentage of `full`.
+    fn channel(&mut self, full: f32) -> Result<f32, ColorParseError> {
+        match self.values.pop().unwrap() {
+            Value::Number(x) => Ok(x),
+            Value::Percent(x) => Ok(x / 100.0 * full),
+            Value::None => Ok(0.0),
+            angle => Err(self.invalid(angle)),
+        }
+    }
+
+    // In degrees.
+    fn hue(&mut self) -> Result<f32, ColorParseError> {
+        match self.values.pop().unwrap() {
+            Value::Number(x) | Value::Angle(x) => Ok(x),
+            Value::None => Ok(0.0),
+            percent => Err(self.invalid(percent)),
+        }
+    }
+
+    // A percentage, which may be written as a bare number, from 0 to 1.
+    fn percent(&mut self) -> Result<f32, ColorParseError> {
+        self.channel(100.0).map(|x| x / 100.0)
+    }
+
+    fn alpha(&self) -> Result<f32, ColorParseError> {
+        match self.alpha {
+            None => Ok(1.0),
+            Some(Value::Number(x)) => Ok(x.clamp(0.0, 1.0)),
+            Some(Value::Percent(x)) => Ok((x / 100.0).clamp(0.0, 1.0)),
+            Some(Value::None) => Ok(0.0),
+            Some(angle) => Err(self.invalid(angle)),
+        }
+    }
+}
+
+// "#rgb", "#rgba", "#rrggbb" or "#rrggbbaa", the '#' being optional.
+pub(super) fn parse_hex(hex: &str) -> Result<Color, ColorParseError> {
+    let invalid = || ColorParseError::Hex(hex.to_owned());
+    let digits = hex.strip_prefix('#').unwrap_or(hex);
+    if !digits.chars().all(|ch| ch.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    let digits: Vec<u8> = match digits.len() {
+        3 | 4 => digits.chars().flat_map(|ch| [ch, ch]).map(|ch| ch as u8).collect(),
+        6 | 8 => digits.bytes().collect(),
+        _ => return Err(invalid()),
+    };
+    let channels: Vec<u8> = digits.chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap())
+        .collect();
+    let alpha = channels.get(3).map_or(1.0, |a| *a as f32 / 255.0);
+    Ok(Color { alpha, ..Color::from_rgb(channels[0], channels[1], channels[2]) })
+}
+
+fn parse_function(function: &str, text: &str) -> Result<Color, ColorParseError> {
+    let mut args = Arguments::parse(function, text)?;
+    let color = match function {
+        "rgb" | "rgba" => {
+            let (r, g, b) = (args.channel(255.0)?, args.channel(255.0)?, args.channel(255.0)?);
+            let [r, g, b] = [r, g, b].map(|x| (x / 255.0).clamp(0.0, 1.0));
+            Color::from_srgb(Srgb::new(r, g, b))
+        }
+        "hsl" | "hsla" => {
+            let (h, s, l) = (args.hue()?, args.percent()?, args.percent()?);
+            Color::from_srgb(Srgb::from_color_unclamped(Hsl::new(h, s.clamp(0.0, 1.0), l.clamp(0.0, 1.0))))
+        }
+        "hwb" => {
+            let (h, w, b) = (args.hue()?, args.percent()?.clamp(0.0, 1.0), args.percent()?.clamp(0.0, 1.0));
+            // whiteness and blackness adding up to more than all make a grey
+            let (w, b) = if w + b > 1.0 { (w / (w + b), b / (w + b)) } else { (w, b) };
+            Color::from_srgb(Srgb::from_color_unclamped(Hwb::new(h, w, b)))
+        }
+        "lab" => {
+            let (l, a, b) = (args.channel(100.0)?, args.channel(125.0)?, args.channel(125.0)?);
+            Color::from_lch(Lch::from_color_unclamped(Lab::new(l.clamp(0.0, 100.0), a, b)))
+        }
+        "lch" => {
+            let (l, c, h) = (args.channel(100.0)?, args.channel(150.0)?, args.hue()?);
+            Color::from_lch(Lch::new(l.clamp(0.0, 100.0), c.max(0.0), h))
+        }
+        "oklch" => {
+            let (l, c, h) = (args.channel(1.0)?, args.channel(0.4)?, args.hue()?);
+            Color::from_lch(Lch::from_color_unclamped(Oklch::new(l.clamp(0.0, 1.0), c.max(0.0), h)))
+        }
+        _ => return Err(ColorParseError::UnknownFunction(function.to_owned())),
+    };
+    Ok(Color { alpha: args.alpha()?, ..color })
+}
+
+fn parse(text: &str) -> Result<Color, ColorParseError> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(ColorParseError::Empty);
+    }
+    if let Some((function, rest)) = text.split_once('(') {
+        let args = rest.strip_suffix(')').ok_or_else(|| ColorParseError::Syntax(text.to_owned()))?;
+        return parse_function(&function.trim().to_ascii_lowercase(), args);
+    }
+    if text.starts_with('#') {
+        return parse_hex(text);
+    }
+    match css_color(text) {
+        Some(hex) => parse_hex(hex),
+        // hex digits without their '#'
+        None => parse_hex(text).map_err(|_| ColorParseError::UnknownName(text.to_owned())),
+    }
+}
+
+impl FromStr for Color {
+    type Err = ColorParseError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        parse(text)
+    }
+}
+
+impl TryFrom<&str> for Color {
+    type Error = ColorParseError;
+
+    fn try_from(text: &str) -> Result<Self, Self::Error> {
+        text.parse()
+    }
+}
+
+impl Color {
+    // The color in CSS `notation`.
+    pub fn display(self, notation: Notation) -> Notated {
+        Notated { color: self, notation }
+    }
+}
+
+// Drops the trailing zeros of a rounded number.
+fn trim(x: f32, decimals: usize) -> String {
+    let text = format!("{:.*}", decimals, x);
+    let text = if text.contains('.') { text.trim_end_matches('0').trim_end_matches('.') } else { &text };
+    if text == "-0" { "0".to_owned() } else { text.to_owned() }
+}
+
+impl fmt::Display for Notated {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let color = self.color;
+        let srgb = space::gamut_map(color);
+        let alpha = if color.alpha < 1.0 { format!(" / {}", trim(color.alpha, 3)) } else { String::new() };
+        match self.notation {
+            Notation::Hex => write!(f, "{}", color.to_hex()),
+            Notation::Rgb => {
+                let (r, g, b) = srgb.into_format::<u8>().into_components();
+                write!(f, "rgb({} {} {}{})", r, g, b, alpha)
+            }
+            Notation::Hsl => {
+                let hsl = Hsl::from_color_unclamped(srgb);
+                write!(f, "hsl({} {}% {}%{})", trim(hsl.hue.to_positive_degrees(), 1),
+                       trim(hsl.saturation * 100.0, 1), trim(hsl.lightness * 100.0, 1), alpha)
+            }
+            Notation::Hwb => {
+                let hwb = Hwb::from_color_unclamped(srgb);
+                write!(f, "hwb({} {}% {}%{})", trim(hwb.hue.to_positive_degrees(), 1),
+                       trim(hwb.whiteness * 100.0, 1), trim(hwb.blackness * 100.0, 1), alpha)
+            }
+            Notation::Lab => {
+                let lab = Lab::from_color_unclamped(color.lch());
+                write!(f, "lab({} {} {}{})", trim(lab.l, 2), trim(lab.a, 2), trim(lab.b, 2), alpha)
+            }
+            Notation::Lch => {
+                let lch = color.lch();
+                write!(f, "lch({} {} {}{})", trim(lch.l, 2), trim(lch.chroma, 2),
+                       trim(lch.hue.to_positive_degrees(), 2), alpha)
+            }
+            Notation::Oklch => {
+                let oklch = Oklch::from_color_unclamped(color.lch());
+                write!(f, "oklch({} {} {}{})", trim(oklch.l, 4), trim(oklch.chroma, 4),
+                       trim(oklch.hue.to_positive_degrees(), 2), alpha)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.display(Notation::Hex))
+    }
+}
+
+#[cfg(test)]
+mod notation_tests {
+    use super::{ColorParseError, Notation, parse};
+    use crate::util::color::Color;
+
+    fn hex(text: &str) -> String {
+        text.parse::<Color>().unwrap().to_hex()
+    }
+
+    #[test]
+    fn hex_forms() {
+        assert_eq!(hex("#fff"), "#ffffff");
+        assert_eq!(hex("#FFF"), "#ffffff");
+        assert_eq!(hex("fff"), "#ffffff");
+        assert_eq!(hex("#de5d83"), "#de5d83");
+        assert_eq!(hex("#ff00ff80"), "#ff00ff80");
+        assert!((parse("#ff00ff80").unwrap().alpha - 128.0 / 255.0).abs() < 1e-6);
+        assert_eq!(parse("#f0f8").unwrap().alpha, 136.0 / 255.0);
+        assert_eq!(hex("#ff00ffff"), "#ff00ff");
+        assert_eq!("#ff00f".parse::<Color>(), Err(ColorParseError::Hex("#ff00f".to_owned())));
+        assert_eq!("#gggggg".parse::<Color>(), Err(ColorParseError::Hex("#gggggg".to_owned())));
+        assert_eq!("".parse::<Color>(), Err(ColorParseError::Empty));
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(hex("rgb(222, 93, 131)"), "#de5d83");
+        assert_eq!(hex("rgba(222 93 131 / 50%)"), "#de5d8380");
+        assert_eq!(hex("rgb(100% 0% 0%)"), "#ff0000");
+        assert_eq!(hex("hsl(120deg 100% 25.1%)"), "#008000");
+        assert_eq!(hex("hsla(0.5turn, 100%, 50%, 0.2)"), "#00ffff33");
+        assert_eq!(hex("hwb(0 0% 0%)"), "#ff0000");
+        assert_eq!(hex("hwb(90 75% 50%)"), "#999999");
+        assert_eq!(hex("lab(100 0 0)"), "#ffffff");
+        assert_eq!(hex("lch(0% none none)"), "#000000");
+        assert_eq!(hex("oklch(1 0 0)"), "#ffffff");
+        assert_eq!(hex("OKLCH(62.8% 0.2577 29.23)"), "#ff0000");
+        assert_eq!(parse("rgb(0 0 0 / 25%)").unwrap().alpha, 0.25);
+
+        assert_eq!("rgb(1 2)".parse::<Color>(),
+                   Err(ColorParseError::Arguments { function: "rgb".to_owned(), expected: 3, found: 2 }));
+        assert_eq!("foo(1 2 3)".parse::<Color>(), Err(ColorParseError::UnknownFunction("foo".to_owned())));
+        assert!(matches!("rgb(1 2 3".parse::<Color>(), Err(ColorParseError::Syntax(_))));
+        assert!(matches!("hsl(10% 2 3)".parse::<Color>(), Err(ColorParseError::Value { .. })));
+        assert!(matches!("rgb(1 2 / 3 4)".parse::<Color>(), Err(ColorParseError::Syntax(_))));
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(hex("rebeccapurple"), "#663399");
+        assert_eq!(hex("AliceBlue"), "#f0f8ff");
+        assert_eq!(Color::try_from("no such color"), Err(ColorParseError::UnknownName("no such color".to_owned())));
+    }
+
+    #[test]
+    fn notations_round_trip() {
+        let color = Color::from_hex("#de5d83");
+        assert_eq!(color.to_string(), "#de5d83");
+        assert_eq!(color.display(Notation::Rgb).to_string(), "rgb(222 93 131)");
+        for notation in Notation::ALL {
+            let text = color.display(notation).to_string();
+            assert_eq!(hex(&text), "#de5d83", "{}", text);
+        }
+
+        let scrim = Color::from_hex("#de5d8380");
+        assert_eq!(scrim.display(Notation::Rgb).to_string(), "rgb(222 93 131 / 0.502)");
+        for notation in Notation::ALL {
+            let text = scrim.display(notation).to_string();
+            assert_eq!(hex(&text), "#de5d8380", "{}", text);
+        }
+    }
+}
@@ -67,6 +67,31 @@ impl RoundedRect {
         let top_pos = self.rect.center() - galley.rect.size()/2.0;
         painter.galley(top_pos, galley);
     }
+
+    // Like `label_inset` on a single line, shrunk to fit the width if it has to.
+    pub fn label_fit(&self, painter: &egui::Painter, text: String, color: Color, size_ratio: Option<f32>) {
+        let size = self.rect.height() * size_ratio.unwrap_or(RoundedRect::DEFAULT_INSET_FRAC);
+        let max_width = self.rect.width() * RoundedRect::DEFAULT_INSET_FRAC;
+        let mut galley = painter.layout_no_wrap(text.clone(), FontId::proportional(size), color.to_color32());
+        if galley.rect.width() > max_width {
+            let size = size * max_width / galley.rect.width();
+            galley = painter.layout_no_wrap(text, FontId::proportional(size), color.to_color32());
+        }
+        let top_pos = self.rect.center() - galley.rect.size()/2.0;
+        painter.galley(top_pos, galley);
+    }
+}
+
+// Light and dark squares for translucent colors to be drawn over.
+pub const CHECKER_COLORS: [Color; 2] = [Color{hue: 0.0, luminance: 0.8, chroma: 0.0, alpha: 1.0},
+                                        Color{hue: 0.0, luminance: 0.6, chroma: 0.0, alpha: 1.0}];
+
+// tesselate a domain with C colors
+pub fn tesselate<const C: usize>(painter: &egui::Painter, domain: RoundedRect, n: usize, cols: [Color; C]) {
+    let rrs = domain.split(n, n);
+    for (index, rr) in rrs.into_iter().enumerate() {
+        painter.rect_filled(rr.rect, rr.rounding, cols[(index + index / n) % C].to_color32());
+    }
 }
 
 impl From<(Rect, Rounding)> for RoundedRect {
@@ -0,0 +1,218 @@
+/*
+ * Working color spaces. `Color` is always stored as normalized CIE LCH, but shades can be taken
+ * along the axes of another space: each space gives a color as luminance, chroma and hue in 0 - 1.
+ *
+ * Colors outside of sRGB are brought into it as CSS Color 4 does: their OKLCH chroma is lowered,
+ * keeping lightness and hue, until clipping what is left changes them by less than a just
+ * noticeable difference.
+ * */
+
+use palette::{convert::FromColorUnclamped, Clamp, Lch, Oklab, Srgb};
+
+use super::color::Color;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WorkingSpace {
+    CieLch,
+    Oklch,
+    Hsluv,
+}
+
+// OKLCH chroma taken as 100%, as in CSS
+const OKLCH_MAX_CHROMA: f32 = 0.4;
+
+// slack for the float error of converting an sRGB color back and forth
+const GAMUT_EPSILON: f32 = 1e-4;
+// deltaEOK under which clipping is not noticed
+const GAMUT_JND: f32 = 0.02;
+const GAMUT_MIN_STEP: f32 = 1e-4;
+
+impl WorkingSpace {
+    pub const ALL: [WorkingSpace; 3] = [WorkingSpace::CieLch, WorkingSpace::Oklch, WorkingSpace::Hsluv];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            WorkingSpace::CieLch => "CIE LCH",
+            WorkingSpace::Oklch => "OKLCH",
+            WorkingSpace::Hsluv => "HSLuv",
+        }
+    }
+
+    // The space with `name`, ignoring case and spaces: "cielch" is CIE LCH.
+    pub fn from_name(name: &str) -> Option<WorkingSpace> {
+        let squash = |name: &str| name.replace(' ', "").to_lowercase();
+        WorkingSpace::ALL.into_iter().find(|space| squash(space.name()) == squash(name))
+    }
+
+    // Short names of the luminance, chroma and hue axes.
+    pub fn axes(self) -> [&'static str; 3] {
+        match self {
+            WorkingSpace::Hsluv => ["L", "S", "H"],
+            _ => ["L", "C", "H"],
+        }
+    }
+
+    // The largest chroma of an sRGB color in this space, normalized chroma can go past 1 in CIE
+    // LCH and stays well below it in OKLCH.
+    pub fn srgb_max_chroma(self) -> f32 {
+        match self {
+            WorkingSpace::CieLch => 1.34,
+            WorkingSpace::Oklch => 0.81,
+            WorkingSpace::Hsluv => 1.0,
+        }
+    }
+
+    // Luminance, chroma and hue of the color in this space, normalized to 0 - 1. HSLuv gives its
+    // saturation as the chroma.
+    pub fn components(self, color: Color) -> [f32; 3] {
+        match self {
+            WorkingSpace::CieLch => [color.luminance, color.chroma, color.hue],
+            WorkingSpace::Oklch => {
+                let oklch = palette::Oklch::from_color_unclamped(color.lch());
+                [oklch.l, oklch.chroma / OKLCH_MAX_CHROMA, oklch.hue.to_positive_degrees() / 360.0]
+            }
+            WorkingSpace::Hsluv => {
+                let hsluv = palette::Hsluv::from_color_unclamped(color.lch());
+                [hsluv.l / 100.0, hsluv.saturation / 100.0, hsluv.hue.to_positive_degrees() / 360.0]
+            }
+        }
+    }
+
+    // An opaque color from its components in this space.
+    pub fn color(self, [luminance, chroma, hue]: [f32; 3]) -> Color {
+        match self {
+            WorkingSpace::CieLch => Color { luminance, chroma, hue, alpha: 1.0 },
+            WorkingSpace::Oklch => {
+                let oklch = palette::Oklch::new(luminance, chroma * OKLCH_MAX_CHROMA, hue * 360.0);
+                Color::from_lch(Lch::from_color_unclamped(oklch))
+            }
+            WorkingSpace::Hsluv => {
+                let hsluv = palette::Hsluv::new(hue * 360.0, chroma * 100.0, luminance * 100.0);
+                Color::from_lch(Lch::from_color_unclamped(hsluv))
+            }
+        }
+    }
+}
+
+fn srgb_in_gamut(srgb: Srgb<f32>) -> bool {
+    let (r, g, b) = srgb.into_components();
+    [r, g, b].iter().all(|x| (-GAMUT_EPSILON..=1.0 + GAMUT_EPSILON).contains(x))
+}
+
+fn delta_eok(a: Oklab<f32>, b: Oklab<f32>) -> f32 {
+    ((a.l - b.l).powi(2) + (a.a - b.a).powi(2) + (a.b - b.b).powi(2)).sqrt()
+}
+
+pub fn in_gamut(color: Color) -> bool {
+    srgb_in_gamut(Srgb::from_color_unclamped(color.lch()))
+}
+
+// Gamma encoded sRGB channels of the color, gamut mapped.
+pub fn gamut_map(color: Color) -> Srgb<f32> {
+    let srgb = Srgb::from_color_unclamped(color.lch());
+    if srgb_in_gamut(srgb) {
+        return srgb.clamp();
+    }
+
+    let origin = palette::Oklch::from_color_unclamped(color.lch());
+    if origin.l >= 1.0 {
+        return Srgb::new(1.0, 1.0, 1.0);
+    }
+    if origin.l <= 0.0 {
+        return Srgb::new(0.0, 0.0, 0.0);
+    }
+
+    let mut clipped = srgb.clamp();
+    if delta_eok(Oklab::from_color_unclamped(clipped), Oklab::from_color_unclamped(origin)) < GAMUT_JND {
+        return clipped;
+    }
+
+    // binary search for the chroma whose clipped color is just noticeably off
+    let (mut min, mut max) = (0.0, origin.chroma);
+    let mut min_in_gamut = true;
+    while max - min > GAMUT_MIN_STEP {
+        let chroma = (min + max) / 2.0;
+        let current = palette::Oklch { chroma, ..origin };
+        let srgb = Srgb::from_color_unclamped(current);
+        if min_in_gamut && srgb_in_gamut(srgb) {
+            min = chroma;
+            continue;
+        }
+        clipped = srgb.clamp();
+        let error = delta_eok(Oklab::from_color_unclamped(clipped), Oklab::from_color_unclamped(current));
+        if error < GAMUT_JND {
+            if GAMUT_JND - error < GAMUT_MIN_STEP {
+                return clipped;
+            }
+            min_in_gamut = false;
+            min = chroma;
+        } else {
+            max = chroma;
+        }
+    }
+    clipped
+}
+
+#[cfg(test)]
+mod space_tests {
+    use palette::{convert::FromColorUnclamped, Oklch, Srgb};
+
+    use super::{WorkingSpace, in_gamut, gamut_map};
+    use crate::util::color::Color;
+
+    #[test]
+    fn components_round_trip() {
+        let color = Color::from_hex("#de5d83");
+        for space in WorkingSpace::ALL {
+            let back = space.color(space.components(color));
+            assert_eq!(back.to_hex(), color.to_hex(), "{}", space.name());
+        }
+        let [l, c, h] = WorkingSpace::Oklch.components(Color::from_hex("#ffffff"));
+        assert!((l - 1.0).abs() < 1e-3 && c < 1e-3, "{} {} {}", l, c, h);
+    }
+
+    #[test]
+    fn names() {
+        for space in WorkingSpace::ALL {
+            assert_eq!(WorkingSpace::from_name(space.name()), Some(space));
+        }
+        assert_eq!(WorkingSpace::from_name("cielch"), Some(WorkingSpace::CieLch));
+        assert_eq!(WorkingSpace::from_name("HSLUV"), Some(WorkingSpace::Hsluv));
+        assert_eq!(WorkingSpace::from_name("lab"), None);
+    }
+
+    #[test]
+    fn srgb_max_chroma() {
+        let corners = ["#ff0000", "#00ff00", "#0000ff", "#ffff00", "#00ffff", "#ff00ff"];
+        for space in WorkingSpace::ALL {
+            let max = corners.iter()
+                .map(|hex| space.components(Color::from_hex(hex))[1])
+                .fold(0.0, f32::max);
+            assert!((max - space.srgb_max_chroma()).abs() < 0.01,
+                    "{} {}", space.name(), max);
+        }
+    }
+
+    #[test]
+    fn mapping_keeps_hue() {
+        for hex in ["#000000", "#ffffff", "#0000ff", "#de5d83"] {
+            let color = Color::from_hex(hex);
+            assert!(in_gamut(color));
+            assert_eq!(color.to_hex(), hex);
+        }
+
+        let vivid = Color { luminance: 0.6, chroma: 1.2, hue: 0.4, alpha: 1.0 };
+        assert!(!in_gamut(vivid));
+        let mapped = gamut_map(vivid);
+        let (r, g, b) = mapped.into_components();
+        assert!([r, g, b].iter().all(|x| (0.0..=1.0).contains(x)));
+
+        let (origin, result) = (Oklch::from_color_unclamped(vivid.lch()), Oklch::from_color_unclamped(mapped));
+        assert!((origin.l - result.l).abs() < 0.03);
+        assert!((origin.hue.to_positive_degrees() - result.hue.to_positive_degrees()).abs() < 5.0);
+        assert!(result.chroma < origin.chroma);
+        // not merely clipped
+        let clipped: Srgb<f32> = Srgb::from_color_unclamped(vivid.lch());
+        assert_ne!(clipped.into_format::<u8>(), mapped.into_format::<u8>());
+    }
+}
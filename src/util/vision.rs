@@ -0,0 +1,112 @@
+/*
+ * Color vision deficiency simulation. The dichromacies use the matrices of Machado, Oliveira and
+ * Fernandes (2009) at full severity, and achromatopsia keeps the luminance alone; both apply to
+ * linear sRGB.
+ *
+ * Widgets draw palette colors with `Color::to_color32_as` and the deficiency the interface is
+ * viewed with, if any.
+ * */
+
+use super::{color::Color, metric::{ColorMetric, Ciede2000}};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Deficiency {
+    Protanopia,
+    Deuteranopia,
+    Tritanopia,
+    Achromatopsia,
+}
+
+type Matrix = [[f32; 3]; 3];
+
+const PROTANOPIA: Matrix = [
+    [ 0.152286,  1.052583, -0.204868],
+    [ 0.114503,  0.786281,  0.099216],
+    [-0.003882, -0.048116,  1.051998],
+];
+const DEUTERANOPIA: Matrix = [
+    [ 0.367322,  0.860646, -0.227968],
+    [ 0.280085,  0.672501,  0.047413],
+    [-0.011820,  0.042940,  0.968881],
+];
+const TRITANOPIA: Matrix = [
+    [ 1.255528, -0.076749, -0.178779],
+    [-0.078411,  0.930809,  0.147602],
+    [ 0.004733,  0.691367,  0.303900],
+];
+// every channel gets the relative luminance
+const ACHROMATOPSIA: Matrix = [[0.2126, 0.7152, 0.0722]; 3];
+
+// CIEDE2000 difference under which two palette colors are taken for one another.
+pub const CONFUSION_DELTA_E: f32 = 8.0;
+
+impl Deficiency {
+    pub const ALL: [Deficiency; 4] = [Deficiency::Protanopia, Deficiency::Deuteranopia,
+                                      Deficiency::Tritanopia, Deficiency::Achromatopsia];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Deficiency::Protanopia => "protanopia",
+            Deficiency::Deuteranopia => "deuteranopia",
+            Deficiency::Tritanopia => "tritanopia",
+            Deficiency::Achromatopsia => "achromatopsia",
+        }
+    }
+
+    // Moves linear sRGB channels to how they are seen, clipped to the gamut.
+    pub fn apply(self, rgb: [f32; 3]) -> [f32; 3] {
+        let matrix = match self {
+            Deficiency::Protanopia => &PROTANOPIA,
+            Deficiency::Deuteranopia => &DEUTERANOPIA,
+            Deficiency::Tritanopia => &TRITANOPIA,
+            Deficiency::Achromatopsia => &ACHROMATOPSIA,
+        };
+        matrix.map(|row| (row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2]).clamp(0.0, 1.0))
+    }
+}
+
+// Pairs of `colors`, by index, that are told apart with normal vision but not with `deficiency`,
+// along with their difference as seen with it.
+pub fn confusions(colors: &[Color], deficiency: Deficiency) -> Vec<(usize, usize, f32)> {
+    let seen: Vec<Color> = colors.iter().map(|color| color.simulate(deficiency)).collect();
+    let mut found = vec![];
+    for i in 0..colors.len() {
+        for j in i + 1..colors.len() {
+            let delta = Ciede2000::distance(&seen[i], &seen[j]);
+            if delta < CONFUSION_DELTA_E && Ciede2000::distance(&colors[i], &colors[j]) >= CONFUSION_DELTA_E {
+                found.push((i, j, delta));
+            }
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod vision_tests {
+    use super::{Deficiency, confusions};
+    use crate::util::color::Color;
+
+    #[test]
+    fn greys_are_kept() {
+        for deficiency in Deficiency::ALL {
+            for hex in ["#000000", "#777777", "#ffffff"] {
+                let grey = Color::from_hex(hex);
+                assert_eq!(grey.simulate(deficiency).to_hex(), hex);
+            }
+        }
+        let red = Color::from_hex("#e03030").simulate(Deficiency::Achromatopsia);
+        assert!(red.chroma < 0.01);
+    }
+
+    #[test]
+    fn red_green_confusion() {
+        let colors = [Color::from_hex("#b84a3a"), Color::from_hex("#5e8030"), Color::from_hex("#2040c0")];
+        let pairs = |deficiency| -> Vec<(usize, usize)> {
+            confusions(&colors, deficiency).into_iter().map(|(i, j, _)| (i, j)).collect()
+        };
+        assert_eq!(pairs(Deficiency::Deuteranopia), [(0, 1)]);
+        assert!(pairs(Deficiency::Tritanopia).is_empty());
+        // both have about the same luminance
+        assert!(pairs(Deficiency::Achromatopsia).contains(&(0, 1)));
+    }
+}
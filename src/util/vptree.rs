@@ -1,153 +1,565 @@
 /* 
- * Implements a (randomized, fixed-seed) vantage-point tree for fast nearest 
- * neighbor calculations, will panic if queried when empty. The `build` 
- * constructor always returns a boxed VPTree. This will take ownership of the data.
+ * Implements a vantage-point tree for fast nearest neighbor calculations. The `build` 
+ * constructor takes ownership of the data. `nearest` will panic if queried when empty, 
+ * the `try_` variants return `None` instead.
+ *
+ * Every node splits the remaining points by their distance to the node's point: the near
+ * subtree holds the points at most `threshold` away, the far subtree those at least 
+ * `threshold` away (the median distance, so ties may land on either side). A search only 
+ * enters a subtree if the ball around the query with the current search radius intersects 
+ * the shell the subtree covers.
+ *
+ * The tree lives in two parallel arenas, `points[i]` being the vantage point of `nodes[i]`, 
+ * and children are indices into them. A fresh build lays the nodes out in preorder, so a 
+ * search walks mostly forward through memory. Building is iterative, and picks as vantage 
+ * point the candidate whose distances to a sample of the range spread the most.
+ *
+ * Points can be inserted and removed after building. Inserted points become new leaves, and
+ * removed points stay in the tree as vantage points, only skipped by searches. Once a subtree
+ * has seen more changes than it had points when it was built, it is rebuilt from its remaining
+ * points, which keeps the height close to that of a freshly built tree. Slots left behind by 
+ * removed points are reclaimed by rebuilding the whole tree once they outnumber the live ones.
  * */
 
+use std::{cmp::Ordering, collections::BinaryHeap, io, mem};
+
 use num::Float;
-use rand::{SeedableRng, RngCore};
 
 pub trait MetricPoint {
     type Dist: Float;
     fn dist(from: &Self, to: &Self) -> Self::Dist;
 }
 
+// Marks a missing child or an empty tree.
+const NONE: usize = usize::MAX;
+
+// Vantage points are chosen among this many candidates, by their distances to this many points
+// of the range. Smaller ranges just take their first point.
+const VANTAGE_CANDIDATES: usize = 5;
+const VANTAGE_SAMPLE: usize = 16;
+const VANTAGE_MIN_RANGE: usize = 32;
+
+#[derive(Debug, Clone)]
+struct Node<D: Float> {
+    removed: bool,
+    threshold: D,
+    near: usize,
+    far: usize,
+    height: usize,
+    // live points in this subtree
+    size: usize,
+    // size when this subtree was built, and insertions and removals since
+    built_size: usize,
+    changes: usize,
+}
+
 #[derive(Debug)]
 pub struct VPTree<D: Float, T: MetricPoint<Dist = D>> {
-    root: T,
-    threshold: D,
-    near: Option<Box<VPTree<D, T>>>,
-    far: Option<Box<VPTree<D, T>>>,
+    points: Vec<T>,
+    nodes: Vec<Node<D>>,
+    root: usize,
+    // slots of removed points that are no longer reachable from the root
+    garbage: usize,
     pub height: usize,
 }
 
-fn to_optref<T>(opt: &Option<Box<T>>) -> Option<&T> {
-    opt.as_ref().map(|b| b.as_ref())
+// One node of a tree flattened into preorder, for storing a built tree. `near` and `far` are
+// positions in the flattened list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlatNode<D, P> {
+    pub point: P,
+    pub threshold: D,
+    pub near: Option<usize>,
+    pub far: Option<usize>,
+    pub removed: bool,
+}
+
+fn cmp_dist<D: Float>(a: &D, b: &D) -> Ordering {
+    a.partial_cmp(b).unwrap_or(Ordering::Equal)
 }
 
-fn split_at<T>(mut vec: Vec<T>, idx: usize) -> (Vec<T>, Vec<T>) {
-    let right = vec.split_off(idx);
-    (vec, right)
+// A point found by a search, ordered by distance so that a `BinaryHeap` of candidates keeps the
+// farthest one on top.
+struct Candidate<'a, D: Float, T> {
+    dist: D,
+    point: &'a T,
+}
+
+impl<D: Float, T> PartialEq for Candidate<'_, D, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.dist == other.dist
+    }
+}
+
+impl<D: Float, T> Eq for Candidate<'_, D, T> {}
+
+impl<D: Float, T> PartialOrd for Candidate<'_, D, T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<D: Float, T> Ord for Candidate<'_, D, T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        cmp_dist(&self.dist, &other.dist)
+    }
+}
+
+impl<D: Float> Node<D> {
+    fn leaf() -> Self {
+        Node {
+            removed: false,
+            threshold: num::zero(),
+            near: NONE,
+            far: NONE,
+            height: 0,
+            size: 1,
+            built_size: 1,
+            changes: 0,
+        }
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.near == NONE && self.far == NONE
+    }
 }
 
 impl<D: Float, T: MetricPoint<Dist = D>> VPTree<D, T> {
     pub fn build(points: Vec<T>) -> Self {
-        *Self::construct(points).unwrap()
+        let n = points.len();
+        let mut tree = VPTree {
+            points,
+            nodes: vec![Node::leaf(); n],
+            root: NONE,
+            garbage: 0,
+            height: 0,
+        };
+
+        let mut order: Vec<usize> = (0..n).collect();
+        tree.root = tree.construct(&mut order);
+        tree.relocate();
+        tree
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root == NONE
     }
 
-    fn construct(mut points: Vec<T>) -> Option<Box<Self>> {
-        if points.is_empty() {
-            return None;
+    pub fn len(&self) -> usize {
+        self.node(self.root).map_or(0, |node| node.size)
+    }
+
+    pub fn insert(&mut self, point: T) {
+        let idx = self.points.len();
+        self.points.push(point);
+        self.nodes.push(Node::leaf());
+
+        // the nodes from the root down to the new leaf's parent
+        let mut path = vec![];
+        let mut at = self.root;
+        while at != NONE {
+            path.push(at);
+            let dist = MetricPoint::dist(&self.points[at], &self.points[idx]);
+            let node = &mut self.nodes[at];
+            if node.is_leaf() {
+                // a leaf splits around its first child
+                node.threshold = dist;
+            }
+            at = if dist <= node.threshold { node.near } else { node.far };
         }
 
-        let n = points.len();
+        match path.last() {
+            None => self.root = idx,
+            Some(&parent) => {
+                let dist = MetricPoint::dist(&self.points[parent], &self.points[idx]);
+                let node = &mut self.nodes[parent];
+                if dist <= node.threshold { node.near = idx } else { node.far = idx }
+            }
+        }
+
+        self.update_path(&path, |node| node.size += 1);
+    }
+
+    // Remove one point equal to `point` and at distance zero from it, returns whether there was
+    // one. Equality alone is not enough: a point type may compare only part of itself.
+    pub fn remove(&mut self, point: &T) -> bool where T: PartialEq {
+        let mut path = vec![];
+        if !self.find(self.root, point, &mut path) {
+            return false;
+        }
+
+        self.nodes[*path.last().unwrap()].removed = true;
+        self.update_path(&path, |node| node.size -= 1);
+        true
+    }
+
+    pub fn nearest<'a>(&'a self, point: &T) -> &'a T {
+        self.try_nearest(point).expect("nearest point queried on an empty VPTree")
+    }
+
+    pub fn try_nearest<'a>(&'a self, point: &T) -> Option<&'a T> {
+        self.k_nearest(point, 1).first().map(|(nearest, _)| *nearest)
+    }
+
+    // The (at most) `k` points closest to `point` with their distances, closest first.
+    pub fn k_nearest<'a>(&'a self, point: &T, k: usize) -> Vec<(&'a T, D)> {
+        if k == 0 {
+            return vec![];
+        }
+
+        let mut heap = BinaryHeap::with_capacity(k + 1);
+        self.k_nearest_impl(self.root, point, k, &mut heap);
+        heap.into_sorted_vec().into_iter().map(|c| (c.point, c.dist)).collect()
+    }
+
+    // All points at most `radius` away from `point` with their distances, closest first.
+    pub fn within<'a>(&'a self, point: &T, radius: D) -> Vec<(&'a T, D)> {
+        let mut found = vec![];
+        self.within_impl(self.root, point, radius, &mut found);
+        found.sort_by(|a, b| cmp_dist(&a.1, &b.1));
+        found
+    }
+
+    // The live points, in no particular order. Every slot is either reachable or removed.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.points.iter().zip(&self.nodes).filter(|(_, node)| !node.removed).map(|(point, _)| point)
+    }
 
-        let mut rng = rand::rngs::SmallRng::seed_from_u64(0xfaceu64);
-        
-        let root_idx = (rng.next_u32() as usize) % n;
-        points.swap(0, root_idx);
-
-        let root = points.swap_remove(0);
-
-        if n == 1 {
-            return Some(Box::new(
-                VPTree{
-                    root, 
-                    threshold: num::zero(), 
-                    near: None, 
-                    far: None,
-                    height: 0
+    // The reachable nodes in preorder, the root first.
+    pub fn flatten(&self) -> Vec<FlatNode<D, &T>> {
+        let (preorder, position) = self.preorder();
+        let moved = |idx: usize| (idx != NONE).then(|| position[idx]);
+        preorder.iter()
+            .map(|&idx| {
+                let node = &self.nodes[idx];
+                FlatNode {
+                    point: &self.points[idx],
+                    threshold: node.threshold,
+                    near: moved(node.near),
+                    far: moved(node.far),
+                    removed: node.removed,
                 }
-            ));
-        }       
+            })
+            .collect()
+    }
 
-        let split_idx = (rng.next_u32() as usize) % (n - 1);
-        let threshold = MetricPoint::dist(&root, &points[split_idx]);
+    // Restore a tree from `flatten`ed nodes, without measuring any distances. The nodes must be
+    // in preorder, every one but the first the child of exactly one earlier node.
+    pub fn from_flat(flat: Vec<FlatNode<D, T>>) -> Result<Self, io::Error> {
+        let n = flat.len();
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
 
-        let mut pos = 0;
+        let mut parent = vec![NONE; n];
+        for (idx, node) in flat.iter().enumerate() {
+            for child in [node.near, node.far].into_iter().flatten() {
+                if child <= idx || child >= n {
+                    return Err(invalid(format!("node {} has child {} out of preorder", idx, child)));
+                }
+                if parent[child] != NONE {
+                    return Err(invalid(format!("node {} has two parents", child)));
+                }
+                parent[child] = idx;
+            }
+        }
+        if let Some(orphan) = (1..n).find(|&idx| parent[idx] == NONE) {
+            return Err(invalid(format!("node {} is not reachable", orphan)));
+        }
+
+        let mut points = Vec::with_capacity(n);
+        let mut nodes = Vec::with_capacity(n);
+        for FlatNode { point, threshold, near, far, removed } in flat {
+            points.push(point);
+            nodes.push(Node {
+                removed,
+                threshold,
+                near: near.unwrap_or(NONE),
+                far: far.unwrap_or(NONE),
+                size: usize::from(!removed),
+                ..Node::leaf()
+            });
+        }
 
-        for i in 0..(n - 1) {
-            if MetricPoint::dist(&root, &points[i]) <= threshold {
-                points.swap(pos, i);
-                pos += 1
+        let mut tree = VPTree { points, nodes, root: if n == 0 { NONE } else { 0 }, garbage: 0, height: 0 };
+        // children come after their parents
+        for idx in (0..n).rev() {
+            tree.update_height(idx);
+            let size = tree.nodes[idx].size;
+            tree.nodes[idx].built_size = size;
+            if parent[idx] != NONE {
+                tree.nodes[parent[idx]].size += size;
             }
         }
+        tree.height = tree.height_of(tree.root);
+        Ok(tree)
+    }
+
+    fn node(&self, idx: usize) -> Option<&Node<D>> {
+        self.nodes.get(idx)
+    }
+
+    fn height_of(&self, idx: usize) -> usize {
+        self.node(idx).map_or(0, |node| node.height)
+    }
 
-        let (left, right) = split_at(points, pos);
-            
-        let near = VPTree::construct(left);
-        let far = VPTree::construct(right);
-            
-        
-        let height = {
-            let mut max = 0;
-            if let Some(near) = &near {
-                max = near.height
+    fn update_height(&mut self, idx: usize) {
+        let node = &self.nodes[idx];
+        let height = if node.is_leaf() {
+            0
+        } else {
+            1 + self.height_of(node.near).max(self.height_of(node.far))
+        };
+        self.nodes[idx].height = height;
+    }
+
+    // The index of the first point of `order` whose distances to the rest spread the most. 
+    fn pick_vantage(&self, order: &[usize]) -> usize {
+        let n = order.len();
+        if n < VANTAGE_MIN_RANGE {
+            return 0;
+        }
+
+        let spread = |candidate: usize| {
+            let dists: Vec<D> = (0..VANTAGE_SAMPLE)
+                .map(|i| order[i * n / VANTAGE_SAMPLE])
+                .map(|other| MetricPoint::dist(&self.points[candidate], &self.points[other]))
+                .collect();
+            let count = D::from(dists.len()).unwrap();
+            let mean = dists.iter().fold(D::zero(), |acc, &d| acc + d) / count;
+            dists.iter().fold(D::zero(), |acc, &d| acc + (d - mean) * (d - mean)) / count
+        };
+
+        (0..VANTAGE_CANDIDATES)
+            .map(|i| (i * n + n / 2) / VANTAGE_CANDIDATES)
+            .map(|pos| (pos, spread(order[pos])))
+            .max_by(|a, b| cmp_dist(&a.1, &b.1))
+            .map_or(0, |(pos, _)| pos)
+    }
+
+    // Build a subtree over the points at the indices in `order`, which is reordered on the way,
+    // and return the index of its root. 
+    fn construct(&mut self, order: &mut [usize]) -> usize {
+        // subranges still to build, with the parent they hang from and on which side
+        let mut stack = vec![(0, order.len(), NONE, true)];
+        let mut built = vec![];
+        let mut root = NONE;
+        let mut rest: Vec<(D, usize)> = Vec::with_capacity(order.len());
+
+        while let Some((lo, hi, parent, near)) = stack.pop() {
+            if lo == hi {
+                continue;
+            }
+
+            let range = &mut order[lo..hi];
+            let vantage = self.pick_vantage(range);
+            range.swap(0, vantage);
+            let idx = range[0];
+
+            // split the rest at the median distance to the vantage point
+            rest.clear();
+            rest.extend(range[1..].iter()
+                .map(|&other| (MetricPoint::dist(&self.points[idx], &self.points[other]), other)));
+            let mut threshold = D::zero();
+            let mut near_len = 0;
+            if !rest.is_empty() {
+                let median = (rest.len() - 1) / 2;
+                rest.select_nth_unstable_by(median, |a, b| cmp_dist(&a.0, &b.0));
+                threshold = rest[median].0;
+                near_len = median + 1;
             }
-            if let Some(far) = &far {
-                max = max.max(far.height)
+            for (slot, &(_, other)) in range[1..].iter_mut().zip(&rest) {
+                *slot = other;
             }
-            max
-        } + 1;
 
-        Some(Box::new(
-            VPTree{
-                root,
+            self.nodes[idx] = Node {
                 threshold,
-                near,
-                far,
-                height,
+                size: hi - lo,
+                built_size: hi - lo,
+                ..Node::leaf()
+            };
+            match (parent, near) {
+                (NONE, _) => root = idx,
+                (parent, true) => self.nodes[parent].near = idx,
+                (parent, false) => self.nodes[parent].far = idx,
             }
-        ))
+            built.push(idx);
+
+            let mid = lo + 1 + near_len;
+            stack.push((mid, hi, idx, false));
+            stack.push((lo + 1, mid, idx, true));
+        }
+
+        // children are built after their parents
+        for &idx in built.iter().rev() {
+            self.update_height(idx);
+        }
+        root
     }
 
-    pub fn nearest<'a, 'b>(&'a self, point: &'b T) -> &'a T {
-        Self::nearest_impl(Some(self), point).unwrap()
+    // The reachable nodes in preorder, and the position of every node in it.
+    fn preorder(&self) -> (Vec<usize>, Vec<usize>) {
+        let mut preorder = Vec::with_capacity(self.len());
+        let mut stack = vec![self.root];
+        while let Some(idx) = stack.pop() {
+            if idx == NONE {
+                continue;
+            }
+            preorder.push(idx);
+            stack.push(self.nodes[idx].far);
+            stack.push(self.nodes[idx].near);
+        }
+
+        let mut position = vec![NONE; self.nodes.len()];
+        for (pos, &idx) in preorder.iter().enumerate() {
+            position[idx] = pos;
+        }
+        (preorder, position)
     }
 
-    fn nearest_impl<'a, 'b>(vptree: Option<&'a Self>, point: &'b T) -> Option<&'a T> {
-        vptree?;
+    // Move the reachable nodes into preorder, dropping the slots of removed points.
+    fn relocate(&mut self) {
+        let (preorder, position) = self.preorder();
+        let moved = |idx: usize| if idx == NONE { NONE } else { position[idx] };
 
-        let vpt = vptree.unwrap();
+        let mut points: Vec<Option<T>> = mem::take(&mut self.points).into_iter().map(Some).collect();
+        self.points = preorder.iter().map(|&idx| points[idx].take().unwrap()).collect();
+        self.nodes = preorder.iter()
+            .map(|&idx| {
+                let node = &self.nodes[idx];
+                Node { near: moved(node.near), far: moved(node.far), ..node.clone() }
+            })
+            .collect();
+        self.root = moved(self.root);
+        self.garbage = 0;
+        self.height = self.height_of(self.root);
+    }
 
-        let root = &vpt.root;
+    // Rebuild the subtree at `idx` from its live points, returns its new root (`NONE` if there
+    // are none left).
+    fn rebuild(&mut self, idx: usize) -> usize {
+        let mut live = vec![];
+        let mut stack = vec![idx];
+        while let Some(at) = stack.pop() {
+            if at == NONE {
+                continue;
+            }
+            let node = &self.nodes[at];
+            if node.removed {
+                self.garbage += 1;
+            } else {
+                live.push(at);
+            }
+            stack.push(node.near);
+            stack.push(node.far);
+        }
+        self.construct(&mut live)
+    }
+
+    // Apply `change` to every node on `path`, bottom up, rebuilding the subtrees that have seen
+    // too many changes and dropping the ones left without points.
+    fn update_path(&mut self, path: &[usize], change: impl Fn(&mut Node<D>)) {
+        let mut replaced = None;
+        for (depth, &idx) in path.iter().enumerate().rev() {
+            if let Some((old, new)) = replaced.take() {
+                let node = &mut self.nodes[idx];
+                if node.near == old { node.near = new } else { node.far = new }
+            }
+
+            let node = &mut self.nodes[idx];
+            change(node);
+            node.changes += 1;
+            self.update_height(idx);
+
+            let node = &self.nodes[idx];
+            let new = if node.size == 0 || node.changes > node.built_size {
+                Some(self.rebuild(idx))
+            } else {
+                None
+            };
+
+            if let Some(new) = new {
+                if depth == 0 {
+                    self.root = new;
+                }
+                replaced = Some((idx, new));
+            }
+        }
+
+        self.height = self.height_of(self.root);
+        if self.garbage > self.len() {
+            self.compact();
+        }
+    }
+
+    // Rebuild the whole tree over the live points only.
+    fn compact(&mut self) {
+        self.root = self.rebuild(self.root);
+        self.relocate();
+    }
+
+    // Collect the path from `idx` down to a live node holding `point`. Points at exactly the
+    // threshold may have gone to either side, so both are searched.
+    fn find(&self, idx: usize, point: &T, path: &mut Vec<usize>) -> bool where T: PartialEq {
+        let Some(node) = self.node(idx) else { return false };
+
+        path.push(idx);
+        let dist = MetricPoint::dist(&self.points[idx], point);
+        if !node.removed && dist == D::zero() && self.points[idx] == *point {
+            return true;
+        }
+
+        if (dist <= node.threshold && self.find(node.near, point, path))
+            || (dist >= node.threshold && self.find(node.far, point, path)) {
+            return true;
+        }
+        path.pop();
+        false
+    }
+
+    // `heap` holds the best `k` candidates so far, the search radius is the distance of the
+    // worst of them once there are `k`.
+    fn k_nearest_impl<'a>(&'a self, idx: usize, point: &T, k: usize, 
+                          heap: &mut BinaryHeap<Candidate<'a, D, T>>) {
+        let Some(node) = self.node(idx) else { return };
+
+        let root = &self.points[idx];
         let root_dist = MetricPoint::dist(root, point);
+        if !node.removed {
+            heap.push(Candidate { dist: root_dist, point: root });
+            if heap.len() > k {
+                heap.pop();
+            }
+        }
 
-        if root_dist <= vpt.threshold {
-            let mut closest =
-                Self::get_closest(point, Some(root), Self::nearest_impl(to_optref(&vpt.near), point));
-            let closest_dist = MetricPoint::dist(closest.unwrap(), point); // this is guaranteed to
-                                                                           // unwrap
-            if closest_dist > vpt.threshold - root_dist {
-                closest = 
-                    Self::get_closest(point, closest, Self::nearest_impl(to_optref(&vpt.far), point));
+        let radius = |heap: &BinaryHeap<Candidate<'a, D, T>>| 
+            if heap.len() < k { D::infinity() } else { heap.peek().unwrap().dist };
+
+        if root_dist <= node.threshold {
+            self.k_nearest_impl(node.near, point, k, heap);
+            if root_dist + radius(heap) >= node.threshold {
+                self.k_nearest_impl(node.far, point, k, heap);
             }
-            closest
         } else {
-            let mut closest = 
-                Self::get_closest(point, Some(root), Self::nearest_impl(to_optref(&vpt.far), point));
-            let closest_dist = MetricPoint::dist(closest.unwrap(), point); // this is guaranteed to
-                                                                           // unwrap
-            if closest_dist > root_dist - vpt.threshold {
-                closest = 
-                    Self::get_closest(point, closest, Self::nearest_impl(to_optref(&vpt.near), point));
+            self.k_nearest_impl(node.far, point, k, heap);
+            if root_dist - radius(heap) <= node.threshold {
+                self.k_nearest_impl(node.near, point, k, heap);
             }
-            closest
-        } 
+        }
     }
 
+    fn within_impl<'a>(&'a self, idx: usize, point: &T, radius: D, found: &mut Vec<(&'a T, D)>) {
+        let Some(node) = self.node(idx) else { return };
 
-    fn get_closest<'a, 'b>(point: &'b T, a: Option<&'a T>, b: Option<&'a T>) -> Option<&'a T> {
-        if a.is_none() {
-            return b
+        let root = &self.points[idx];
+        let root_dist = MetricPoint::dist(root, point);
+        if !node.removed && root_dist <= radius {
+            found.push((root, root_dist));
         }
-        if b.is_none() {
-            return a
+
+        if root_dist - radius <= node.threshold {
+            self.within_impl(node.near, point, radius, found);
         }
-        if MetricPoint::dist(a.unwrap(), point) <= MetricPoint::dist(b.unwrap(), point) {
-            a
-        } else {
-            b
+        if root_dist + radius >= node.threshold {
+            self.within_impl(node.far, point, radius, found);
         }
     }
 }
@@ -157,7 +569,9 @@ impl<D: Float, T: MetricPoint<Dist = D>> VPTree<D, T> {
 #[cfg(test)]
 mod vptree_tests {
     
-    use crate::util::vptree::{VPTree, MetricPoint};
+    use rand::{Rng, SeedableRng};
+
+    use crate::util::vptree::{VPTree, MetricPoint, FlatNode};
 
     impl MetricPoint for [f64; 2] {
         type Dist = f64;
@@ -173,4 +587,174 @@ mod vptree_tests {
         let vp = VPTree::build(data.to_vec());
         assert_eq!(vp.nearest(&[4.0, 3.0]), &[2., 1.]);
     }
+
+    #[test]
+    fn vptree_empty() {
+        let vp: VPTree<f64, [f64; 2]> = VPTree::build(vec![]);
+        assert!(vp.is_empty());
+        assert!(vp.try_nearest(&[0., 0.]).is_none());
+        assert!(vp.k_nearest(&[0., 0.], 3).is_empty());
+        assert!(vp.within(&[0., 0.], 1.0).is_empty());
+    }
+
+    fn random_points(rng: &mut impl Rng, n: usize) -> Vec<[f64; 2]> {
+        // a coarse grid, so that equal distances come up
+        (0..n).map(|_| [rng.gen_range(0..20) as f64, rng.gen_range(0..20) as f64]).collect()
+    }
+
+    fn brute_force(points: &[[f64; 2]], query: &[f64; 2]) -> Vec<f64> {
+        let mut dists: Vec<f64> = points.iter().map(|p| MetricPoint::dist(p, query)).collect();
+        dists.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        dists
+    }
+
+    // Compare every query against a linear scan over random trees of random sizes. Ties may be
+    // broken differently, so only the distances are compared.
+    #[test]
+    fn vptree_matches_brute_force() {
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(0xbeef);
+
+        for _ in 0..200 {
+            let n = rng.gen_range(0..100);
+            let points = random_points(&mut rng, n);
+            let vp = VPTree::build(points.clone());
+
+            for _ in 0..10 {
+                let query = [rng.gen_range(-5.0..25.0), rng.gen_range(-5.0..25.0)];
+                let expected = brute_force(&points, &query);
+
+                let nearest = vp.try_nearest(&query).map(|p| MetricPoint::dist(p, &query));
+                assert_eq!(nearest, expected.first().copied());
+
+                let k = rng.gen_range(0..12);
+                let knn: Vec<f64> = vp.k_nearest(&query, k).into_iter().map(|(_, d)| d).collect();
+                assert_eq!(knn, expected.iter().copied().take(k).collect::<Vec<f64>>());
+
+                let radius = rng.gen_range(0.0..8.0);
+                let within: Vec<f64> = vp.within(&query, radius).into_iter().map(|(_, d)| d).collect();
+                assert_eq!(within, expected.iter().copied().filter(|d| *d <= radius).collect::<Vec<f64>>());
+            }
+        }
+    }
+
+    #[test]
+    fn vptree_insert_remove() {
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(0xcafe);
+        let mut points = random_points(&mut rng, 50);
+        let mut vp = VPTree::build(points.clone());
+
+        for _ in 0..2000 {
+            if points.is_empty() || rng.gen_bool(0.6) {
+                let point = random_points(&mut rng, 1)[0];
+                vp.insert(point);
+                points.push(point);
+            } else {
+                let point = points.swap_remove(rng.gen_range(0..points.len()));
+                assert!(vp.remove(&point));
+            }
+            assert!(!vp.remove(&[-1.0, -1.0]));
+            assert_eq!(vp.len(), points.len());
+            assert_eq!(vp.iter().count(), points.len());
+
+            let query = [rng.gen_range(-5.0..25.0), rng.gen_range(-5.0..25.0)];
+            let expected = brute_force(&points, &query);
+            let nearest = vp.try_nearest(&query).map(|p| MetricPoint::dist(p, &query));
+            assert_eq!(nearest, expected.first().copied());
+            let knn: Vec<f64> = vp.k_nearest(&query, 5).into_iter().map(|(_, d)| d).collect();
+            assert_eq!(knn, expected.iter().copied().take(5).collect::<Vec<f64>>());
+        }
+    }
+
+    // Equal by tag only, like named colors are equal by name.
+    #[derive(Clone, Copy, Debug)]
+    struct Tagged([f64; 2], u8);
+
+    impl PartialEq for Tagged {
+        fn eq(&self, other: &Self) -> bool {
+            self.1 == other.1
+        }
+    }
+
+    impl MetricPoint for Tagged {
+        type Dist = f64;
+
+        fn dist(from: &Self, to: &Self) -> Self::Dist {
+            MetricPoint::dist(&from.0, &to.0)
+        }
+    }
+
+    #[test]
+    fn vptree_remove_needs_same_position() {
+        let points: Vec<Tagged> = (0..20).map(|i| Tagged([i as f64, 0.0], (i % 2) as u8)).collect();
+        let mut vp = VPTree::build(points);
+
+        assert!(!vp.remove(&Tagged([4.5, 1.0], 0)));
+        assert!(vp.remove(&Tagged([7.0, 0.0], 1)));
+        assert_eq!(vp.len(), 19);
+        assert_ne!(vp.nearest(&Tagged([7.0, 0.0], 1)).0, [7.0, 0.0]);
+    }
+
+    // Points on a line, inserted in order, would form a single path without rebuilds.
+    #[test]
+    fn vptree_height_stays_bounded() {
+        let mut vp = VPTree::build(vec![]);
+        for i in 0..4096 {
+            vp.insert([i as f64, 0.0]);
+        }
+        assert_eq!(vp.len(), 4096);
+        // 3 * log2(4096)
+        assert!(vp.height <= 36, "height {}", vp.height);
+
+        for i in 0..4000 {
+            assert!(vp.remove(&[i as f64, 0.0]));
+        }
+        assert_eq!(vp.len(), 96);
+        assert_eq!(vp.nearest(&[0.0, 0.0]), &[4000.0, 0.0]);
+    }
+
+    #[test]
+    fn vptree_flat_round_trip() {
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(0xd00d);
+        let points = random_points(&mut rng, 300);
+        let mut vp = VPTree::build(points.clone());
+        for point in &points[..30] {
+            vp.remove(point);
+        }
+
+        let flat: Vec<FlatNode<f64, [f64; 2]>> = vp.flatten().into_iter()
+            .map(|FlatNode { point, threshold, near, far, removed }| 
+                 FlatNode { point: *point, threshold, near, far, removed })
+            .collect();
+        let restored = VPTree::from_flat(flat.clone()).unwrap();
+        assert_eq!(restored.len(), vp.len());
+        assert_eq!(restored.height, vp.height);
+
+        let query = [7.5, 3.0];
+        assert_eq!(restored.k_nearest(&query, 8), vp.k_nearest(&query, 8));
+
+        let mut cyclic = flat;
+        cyclic[5].near = Some(0);
+        assert!(VPTree::from_flat(cyclic).is_err());
+    }
+
+    // Median splits halve every range, and removals give their slots back once they pile up.
+    #[test]
+    fn vptree_balanced_and_compacted() {
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(0xf00d);
+        let mut points: Vec<[f64; 2]> = (0..1024)
+            .map(|_| [rng.gen_range(0.0..1.0), rng.gen_range(0.0..1.0)])
+            .collect();
+        let mut vp = VPTree::build(points.clone());
+        assert!(vp.height <= 10, "height {}", vp.height);
+
+        for point in points.drain(100..) {
+            assert!(vp.remove(&point));
+        }
+        assert_eq!(vp.len(), 100);
+        assert!(vp.points.len() <= 2 * 100 + 1, "{} slots", vp.points.len());
+
+        let query = [0.5, 0.5];
+        let nearest = MetricPoint::dist(vp.nearest(&query), &query);
+        assert_eq!(nearest, brute_force(&points, &query)[0]);
+    }
 }
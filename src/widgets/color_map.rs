@@ -1,27 +1,51 @@
-use std::f32::INFINITY;
+use std::collections::HashMap;
 
-use egui::{Widget, Ui, Rounding, Sense, vec2, Rect, pos2, Color32, Pos2};
+use egui::{Widget, Ui, Rounding, Sense, vec2, Rect, pos2, Pos2};
 
-use crate::{state::{Chan, Message, MapData, Location}, util::{color::Color, RoundedRect}};
+use crate::{state::{Chan, Message, MapData, Location}, util::{color::{Color, ColorDB}, vision::Deficiency, RoundedRect, 
+                   tesselate, CHECKER_COLORS}};
 
-pub struct ColorMap {}
+use super::color_names::{nearest_name, candidates_tooltip};
+
+pub struct ColorMap {
+    // nearest names of the cell colors, with the color they were looked up for
+    names: HashMap<Location, (Color, String)>,
+    // size of the database the names come from
+    db_len: usize,
+}
 
 fn make_rounding(radius: f32) -> Rounding {
     Rounding::same(radius)
 }
 
-const MOUSE_FAR_AWAY: Pos2 = pos2(INFINITY, INFINITY);
+const MOUSE_FAR_AWAY: Pos2 = pos2(f32::INFINITY, f32::INFINITY);
 
 impl ColorMap {
 
     const ROUND_RADIUS_FRAC: f32 = 0.05;
+    const LINK_MARKER_FRAC: f32 = 0.08;
+    // share of the cell height below the index label taken by the name
+    const NAME_FRAC: f32 = 0.25;
 
     pub fn new() -> Self {
-        ColorMap { }
+        ColorMap { names: HashMap::new(), db_len: 0 }
     }
 
-    pub fn construct<'a>(&'a mut self, map: &'a MapData, chan: &'a mut Chan, 
-                         max_width: f32, max_height: f32) -> impl Widget + 'a {
+    fn name_of(&mut self, db: &ColorDB, loc: Location, color: Color) -> &str {
+        if db.len() != self.db_len {
+            self.names.clear();
+            self.db_len = db.len();
+        }
+        let entry = self.names.entry(loc).or_insert_with(|| (color, nearest_name(db, color)));
+        if entry.0 != color {
+            *entry = (color, nearest_name(db, color));
+        }
+        &entry.1
+    }
+
+    // Cells are drawn as seen with `view`, if any.
+    pub fn construct<'a>(&'a mut self, map: &'a MapData, db: Option<&'a ColorDB>, view: Option<Deficiency>,
+                         chan: &'a mut Chan, max_width: f32, max_height: f32) -> impl Widget + 'a {
         move |ui: &mut Ui| -> egui::Response {
             let (rows, cols) = map.size();
             let aspect_ratio = cols as f32 / rows as f32;
@@ -51,11 +75,16 @@ impl ColorMap {
                     let cell_rect = Rect::from_min_size(pos2(cell_left, cell_top), vec2(each_width, each_height));
 
                     if let Some(cc) = cell_color {
-                        valid_cell(painter, (cell_rect, rounding).into(), cc, (r, c), 
-                                   map, chan, mouse, click);
+                        let name = db.map(|db| self.name_of(db, (r, c), cc).to_owned());
+                        valid_cell(painter, (cell_rect, rounding).into(), cc.seen_as(view), (r, c), 
+                                   map, name, chan, mouse, click);
+
+                        if let Some(db) = db.filter(|_| response.hovered() && cell_rect.contains(mouse)) {
+                            candidates_tooltip(&response.ctx, id.with((r, c)), db, cc, view);
+                        }
                     } else {
                         invalid_cell(painter, (cell_rect, rounding).into(), (r, c), 
-                                     map, chan, mouse, click); 
+                                     chan, mouse, click); 
                     }
                 }
             }
@@ -77,9 +106,13 @@ fn select_color(color: Color) -> Color {
     Color{luminance: lum, ..color}
 }
 
+#[allow(clippy::too_many_arguments)]
 fn valid_cell(painter: &egui::Painter, rr: RoundedRect, color: Color, loc: Location, 
-              map: &MapData, chan: &mut Chan, mouse: Pos2, click: bool) {
+              map: &MapData, name: Option<String>, chan: &mut Chan, mouse: Pos2, click: bool) {
     let RoundedRect{ rect, rounding } = rr;
+    if color.alpha < 1.0 {
+        tesselate(painter, rr, TESSELATE_LEVEL, CHECKER_COLORS);
+    }
     painter.rect_filled(rect, rounding, color.to_color32());
 
     let button_color = color.accent_color();
@@ -95,18 +128,32 @@ fn valid_cell(painter: &egui::Painter, rr: RoundedRect, color: Color, loc: Locat
         if click {
             chan.push(Message::DeleteColor { loc });
         }
+    } else if let Some(name) = name {
+        // the index label moves up to make room for the name below it
+        let split = rect.bottom() - rect.height() * ColorMap::NAME_FRAC;
+        let label_rr = RoundedRect { rect: Rect::from_x_y_ranges(rect.x_range(), rect.top()..=split), rounding };
+        let name_rr = RoundedRect { rect: Rect::from_x_y_ranges(rect.x_range(), split..=rect.bottom()), rounding };
+        label_rr.label_inset(painter, map.index_at(loc).unwrap(), button_color, None);
+        name_rr.label_fit(painter, name, button_color, Some(0.6));
     } else {
         rr.label_inset(painter, map.index_at(loc).unwrap(), button_color, None);
     }
+
+    // derived cells get a dot in their corner
+    if map.link_at(loc).is_some() {
+        let radius = rect.width() * ColorMap::LINK_MARKER_FRAC;
+        let center = rect.left_top() + vec2(radius, radius) * 2.0;
+        painter.circle_filled(center, radius, color.borw());
+    }
 }
 
-const INVALID_COLOR_DARK: Color = Color{hue: 0.0, luminance: 0.3, chroma: 0.0};
-const INVALID_COLOR_LIGHT: Color = Color{hue: 0.0, luminance: 0.4, chroma: 0.0};
+const INVALID_COLOR_DARK: Color = Color{hue: 0.0, luminance: 0.3, chroma: 0.0, alpha: 1.0};
+const INVALID_COLOR_LIGHT: Color = Color{hue: 0.0, luminance: 0.4, chroma: 0.0, alpha: 1.0};
 const TESSELATE_LEVEL: usize = 4;
 
 fn invalid_cell(painter: &egui::Painter, rr: RoundedRect, loc: Location, 
-                map: &MapData, chan: &mut Chan, mouse: Pos2, click: bool) {
-    let RoundedRect{ rect, rounding } = rr;
+                chan: &mut Chan, mouse: Pos2, click: bool) {
+    let RoundedRect{ rect, .. } = rr;
     if rect.contains(mouse) {
         tesselate(painter, rr, TESSELATE_LEVEL, 
                   [INVALID_COLOR_LIGHT, INVALID_COLOR_DARK]);
@@ -119,11 +166,3 @@ fn invalid_cell(painter: &egui::Painter, rr: RoundedRect, loc: Location,
                   [select_color(INVALID_COLOR_DARK), select_color(INVALID_COLOR_LIGHT)]);
     }
 }
-
-// tesselate a domain with C colors
-fn tesselate<const C: usize>(painter: &egui::Painter, domain: RoundedRect, n: usize, cols: [Color; C]) {
-    let rrs = domain.split(n, n);
-    for (index, rr) in rrs.into_iter().enumerate() {
-        painter.rect_filled(rr.rect, rr.rounding, cols[(index + index / n) % C].to_color32());
-    }
-}
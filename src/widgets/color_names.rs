@@ -0,0 +1,31 @@
+/* Nearest names from the color database, as labels and as hover tooltips. */
+
+use egui::{Context, Id, RichText};
+
+use crate::util::{color::{Color, ColorDB, NamedColor, quantize_color}, vision::Deficiency};
+
+// Candidates listed in a tooltip.
+const CANDIDATES: usize = 5;
+
+pub fn nearest_name(db: &ColorDB, color: Color) -> String {
+    quantize_color(db, color).name().to_owned()
+}
+
+// The closest names to `color` next to the pointer, with their dictionaries and CIEDE2000 
+// distances. Swatches are drawn as seen with `view`, if any.
+pub fn candidates_tooltip(ctx: &Context, id: Id, db: &ColorDB, color: Color, view: Option<Deficiency>) {
+    let query = NamedColor::new(color, String::new());
+    egui::show_tooltip_at_pointer(ctx, id, |ui| {
+        egui::Grid::new(id.with("candidates")).show(ui, |ui| {
+            for (named, dist) in db.k_nearest(&query, CANDIDATES) {
+                let swatch = named.color();
+                ui.label(RichText::new("■").color(swatch.to_color32_as(view)));
+                ui.label(named.name());
+                ui.label(swatch.to_hex());
+                ui.label(format!("ΔE {:.1}", dist));
+                ui.weak(named.source().map_or("", |source| source.name()));
+                ui.end_row();
+            }
+        });
+    });
+}
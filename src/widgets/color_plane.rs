@@ -0,0 +1,217 @@
+/*
+ * A plane of the working space through the picked color: hue against chroma at its luminance, or
+ * luminance against chroma at its hue. It is drawn as a shaded mesh with the edge of the sRGB gamut
+ * traced over it; clicking or dragging on it picks the color under the pointer.
+ * */
+
+use egui::{Ui, Sense, vec2, pos2, Pos2, Rect, Mesh, Shape, Stroke, Color32, emath::remap_clamp};
+
+use crate::{state::{Chan, Message}, util::{color::Color, space::WorkingSpace, vision::Deficiency}};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlaneMode {
+    HueChroma,
+    LuminanceChroma,
+}
+
+// The plane a cache was computed for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Plane {
+    mode: PlaneMode,
+    space: WorkingSpace,
+    // the luminance, or the hue, every point of the plane shares
+    fixed: f32,
+}
+
+pub struct ColorPlane {
+    mode: PlaneMode,
+    // colors at the mesh vertices, and the largest chroma in gamut along the plane
+    cache: Option<(Plane, Vec<Color>, Vec<f32>)>,
+}
+
+impl ColorPlane {
+    // mesh cells along each side
+    const STEPS: usize = 48;
+    const GAMUT_SEARCH_STEPS: usize = 16;
+    const ASPECT_RATIO: f32 = 1.5;
+    const MARKER_RADIUS: f32 = 5.0;
+    const EDGE_WIDTH: f32 = 2.0;
+    // below this chroma the hue of a color means nothing, and the plane keeps its hue
+    const ACHROMATIC: f32 = 1e-3;
+
+    pub fn new() -> Self {
+        ColorPlane { mode: PlaneMode::HueChroma, cache: None }
+    }
+
+    // Components of the point at `along` (the hue or the luminance) and `chroma`, both 0 - 1. The
+    // chroma axis spans every chroma sRGB reaches in the space.
+    fn components(plane: Plane, along: f32, chroma: f32) -> [f32; 3] {
+        let chroma = chroma * plane.space.srgb_max_chroma();
+        match plane.mode {
+            PlaneMode::HueChroma => [plane.fixed, chroma, along],
+            PlaneMode::LuminanceChroma => [along, chroma, plane.fixed],
+        }
+    }
+
+    fn pos(mode: PlaneMode, rect: Rect, along: f32, chroma: f32) -> Pos2 {
+        let (x, y) = match mode {
+            PlaneMode::HueChroma => (along, chroma),
+            PlaneMode::LuminanceChroma => (chroma, along),
+        };
+        pos2(remap_clamp(x, 0.0..=1.0, rect.x_range()), remap_clamp(y, 0.0..=1.0, rect.bottom()..=rect.top()))
+    }
+
+    // (along, chroma) under `pos`.
+    fn at(mode: PlaneMode, rect: Rect, pos: Pos2) -> (f32, f32) {
+        let x = remap_clamp(pos.x, rect.x_range(), 0.0..=1.0);
+        let y = remap_clamp(pos.y, rect.bottom()..=rect.top(), 0.0..=1.0);
+        match mode {
+            PlaneMode::HueChroma => (x, y),
+            PlaneMode::LuminanceChroma => (y, x),
+        }
+    }
+
+    // The largest chroma in gamut at `along`, assuming the gamut holds every chroma below it.
+    fn max_chroma(plane: Plane, along: f32) -> f32 {
+        let in_gamut = |chroma| plane.space.color(Self::components(plane, along, chroma)).in_gamut();
+        if in_gamut(1.0) {
+            return 1.0;
+        }
+        let (mut low, mut high) = (0.0, 1.0);
+        for _ in 0..Self::GAMUT_SEARCH_STEPS {
+            let mid = (low + high) / 2.0;
+            if in_gamut(mid) { low = mid } else { high = mid }
+        }
+        low
+    }
+
+    fn compute(plane: Plane) -> (Vec<Color>, Vec<f32>) {
+        let step = 1.0 / Self::STEPS as f32;
+        let colors = (0..=Self::STEPS)
+            .flat_map(|i| (0..=Self::STEPS).map(move |j| (i as f32 * step, j as f32 * step)))
+            .map(|(along, chroma)| plane.space.color(Self::components(plane, along, chroma)))
+            .collect();
+        let edge = (0..=Self::STEPS).map(|i| Self::max_chroma(plane, i as f32 * step)).collect();
+        (colors, edge)
+    }
+
+    fn plane(&self, color: Color, space: WorkingSpace) -> Plane {
+        let [luminance, chroma, hue] = space.components(color);
+        let fixed = match self.mode {
+            PlaneMode::HueChroma => luminance,
+            PlaneMode::LuminanceChroma => match self.cache {
+                Some((plane, ..)) if chroma < Self::ACHROMATIC && plane.mode == self.mode && plane.space == space =>
+                    plane.fixed,
+                _ => hue,
+            },
+        };
+        Plane { mode: self.mode, space, fixed }
+    }
+
+    // The plane is drawn as seen with `view`, if any.
+    #[allow(clippy::too_many_arguments)]
+    pub fn place(&mut self, ui: &mut Ui, color: Color, space: WorkingSpace, view: Option<Deficiency>,
+                 chan: &mut Chan, max_width: f32, max_height: f32) {
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.mode, PlaneMode::HueChroma, "hue × chroma");
+            ui.selectable_value(&mut self.mode, PlaneMode::LuminanceChroma, "luminance × chroma");
+        });
+
+        let plane = self.plane(color, space);
+        if self.cache.as_ref().is_none_or(|(cached, ..)| *cached != plane) {
+            let (colors, edge) = Self::compute(plane);
+            self.cache = Some((plane, colors, edge));
+        }
+        let (_, colors, edge) = self.cache.as_ref().unwrap();
+
+        let width = max_width.min(max_height * Self::ASPECT_RATIO);
+        let (rect, response) = ui.allocate_exact_size(vec2(width, width / Self::ASPECT_RATIO), Sense::click_and_drag());
+        let painter = ui.painter_at(rect);
+        let step = 1.0 / Self::STEPS as f32;
+
+        let mut mesh = Mesh::default();
+        for i in 0..=Self::STEPS {
+            for j in 0..=Self::STEPS {
+                let pos = Self::pos(plane.mode, rect, i as f32 * step, j as f32 * step);
+                mesh.colored_vertex(pos, colors[i * (Self::STEPS + 1) + j].to_color32_as(view));
+            }
+        }
+        let side = Self::STEPS as u32 + 1;
+        for i in 0..Self::STEPS as u32 {
+            for j in 0..Self::STEPS as u32 {
+                let corner = i * side + j;
+                mesh.add_triangle(corner, corner + 1, corner + side);
+                mesh.add_triangle(corner + 1, corner + side + 1, corner + side);
+            }
+        }
+        painter.add(Shape::mesh(mesh));
+
+        let edge: Vec<Pos2> = edge.iter()
+            .enumerate()
+            .map(|(i, chroma)| Self::pos(plane.mode, rect, i as f32 * step, *chroma))
+            .collect();
+        painter.add(Shape::line(edge.clone(), Stroke::new(Self::EDGE_WIDTH * 2.0, Color32::BLACK)));
+        painter.add(Shape::line(edge, Stroke::new(Self::EDGE_WIDTH, Color32::WHITE)));
+
+        let [luminance, chroma, hue] = space.components(color);
+        let along = match plane.mode {
+            PlaneMode::HueChroma => hue,
+            PlaneMode::LuminanceChroma => luminance,
+        };
+        let chroma = chroma / space.srgb_max_chroma();
+        painter.circle_stroke(Self::pos(plane.mode, rect, along, chroma), Self::MARKER_RADIUS,
+                              Stroke::new(Self::EDGE_WIDTH, color.seen_as(view).borw()));
+
+        if response.clicked() || response.dragged() {
+            if let Some(pos) = response.interact_pointer_pos() {
+                let (along, chroma) = Self::at(plane.mode, rect, pos);
+                let to = Color { alpha: color.alpha, ..space.color(Self::components(plane, along, chroma)) };
+                if to != color {
+                    chan.push(Message::ChangeColor { to });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod color_plane_tests {
+    use egui::{pos2, Rect};
+
+    use super::{ColorPlane, Plane, PlaneMode};
+    use crate::util::{color::Color, space::WorkingSpace};
+
+    #[test]
+    fn positions_round_trip() {
+        let rect = Rect::from_min_max(pos2(10.0, 20.0), pos2(310.0, 220.0));
+        for mode in [PlaneMode::HueChroma, PlaneMode::LuminanceChroma] {
+            for (along, chroma) in [(0.0, 0.0), (0.25, 0.8), (1.0, 0.5), (0.6, 1.0)] {
+                let (a, c) = ColorPlane::at(mode, rect, ColorPlane::pos(mode, rect, along, chroma));
+                assert!((a - along).abs() < 1e-5 && (c - chroma).abs() < 1e-5, "{:?}", mode);
+            }
+        }
+        // chroma grows upwards on the hue plane and rightwards on the luminance one
+        assert_eq!(ColorPlane::pos(PlaneMode::HueChroma, rect, 0.0, 1.0).y, rect.top());
+        assert_eq!(ColorPlane::pos(PlaneMode::LuminanceChroma, rect, 0.0, 1.0).x, rect.right());
+    }
+
+    // Blue is past a chroma of 1 in CIE LCH, the edge has to reach it.
+    #[test]
+    fn gamut_edge() {
+        let space = WorkingSpace::CieLch;
+        let blue = Color::from_hex("#0000ff");
+        assert!(blue.chroma > 1.0);
+
+        let plane = Plane { mode: PlaneMode::HueChroma, space, fixed: blue.luminance };
+        let edge = ColorPlane::max_chroma(plane, blue.hue);
+        let at_edge = space.color(ColorPlane::components(plane, blue.hue, edge));
+        assert!((at_edge.chroma - blue.chroma).abs() < 0.01, "{} {}", at_edge.chroma, blue.chroma);
+        assert!(at_edge.in_gamut());
+        assert!(!space.color(ColorPlane::components(plane, blue.hue, edge + 0.01)).in_gamut());
+
+        // along blue's hue, the edge is at the top of the axis at its luminance and at 0 for black
+        let plane = Plane { mode: PlaneMode::LuminanceChroma, space, fixed: blue.hue };
+        assert!(ColorPlane::max_chroma(plane, 0.0) < 1e-3);
+        assert!(ColorPlane::max_chroma(plane, blue.luminance) > 0.99);
+    }
+}
@@ -0,0 +1,125 @@
+/*
+ * Contrast of every pair of palette colors, with the row color as text on the column color.
+ * Every tile previews the pair with its WCAG ratio, and a badge per WCAG level that is green
+ * when the pair passes it; hovering a tile also gives the APCA contrast. Clicking a tile that 
+ * fails the chosen target level moves the text color's luminance just far enough to pass it, unless
+ * the text color is derived from another cell: changing it would break its relation.
+ * */
+
+use egui::{Ui, Sense, vec2, Rounding, Color32, FontId, Align2, Rect};
+
+use crate::{state::{MapData, Chan, Message}, util::{color::{Color, ContrastLevel, fix_contrast}, vision::Deficiency, RoundedRect}};
+
+use super::ColorLabel;
+
+pub struct ContrastMatrix {
+    target: ContrastLevel,
+}
+
+const PASS_COLOR: Color32 = Color32::from_rgb(0x2e, 0x7d, 0x32);
+const FAIL_COLOR: Color32 = Color32::from_rgb(0xc6, 0x28, 0x28);
+
+impl ContrastMatrix {
+    const TILE_WIDTH: f32 = 128.0;
+    const TILE_HEIGHT: f32 = 64.0;
+    const ROUND_RADIUS_FRAC: f32 = 0.08;
+    // share of the tile height taken by the badges at its bottom
+    const BADGE_FRAC: f32 = 0.25;
+    const MAX_HEIGHT: f32 = 400.0;
+
+    pub fn new() -> Self {
+        ContrastMatrix { target: ContrastLevel::Aa }
+    }
+
+    // Returns the fixed text color when the tile was clicked, if the text color can be fixed.
+    fn tile(&self, ui: &mut Ui, text: Color, background: Color, fixable: bool, 
+            view: Option<Deficiency>) -> Option<Color> {
+        let (rect, response) = ui.allocate_exact_size(vec2(Self::TILE_WIDTH, Self::TILE_HEIGHT), Sense::click());
+        let painter = ui.painter();
+        let rounding = Rounding::same(Self::TILE_WIDTH * Self::ROUND_RADIUS_FRAC);
+        let ratio = text.contrast_ratio(background);
+
+        painter.rect_filled(rect, rounding, background.to_color32_as(view));
+
+        let split = rect.bottom() - rect.height() * Self::BADGE_FRAC;
+        let sample = RoundedRect { rect: Rect::from_x_y_ranges(rect.x_range(), rect.top()..=split), rounding };
+        sample.label_fit(painter, format!("Aa {:.2}", ratio), text.seen_as(view), Some(0.5));
+
+        let badges = RoundedRect { rect: Rect::from_x_y_ranges(rect.x_range(), split..=rect.bottom()), rounding };
+        for (rr, level) in badges.split(1, ContrastLevel::ALL.len()).into_iter().zip(ContrastLevel::ALL) {
+            let fill = if level.passes(ratio) { PASS_COLOR } else { FAIL_COLOR };
+            painter.rect_filled(rr.rect.shrink(1.0), rr.rounding, fill);
+            painter.text(rr.rect.center(), Align2::CENTER_CENTER, level.short_name(),
+                         FontId::proportional(rr.rect.height() * 0.6), Color32::WHITE);
+        }
+
+        let needs_fix = !self.target.passes(ratio);
+        let fixed = (needs_fix && fixable && response.clicked())
+            .then(|| fix_contrast(text, background, self.target.min_ratio()))
+            .flatten();
+
+        response.on_hover_ui(|ui| {
+            ui.label(format!("{} on {}", text.to_hex(), background.to_hex()));
+            ui.label(format!("WCAG {:.2}:1", ratio));
+            ui.label(format!("APCA Lc {:.1}", text.apca_contrast(background)));
+            for level in ContrastLevel::ALL {
+                let pass = level.passes(ratio);
+                let verdict = if pass { "pass" } else { "fail" };
+                ui.colored_label(if pass { PASS_COLOR } else { FAIL_COLOR }, format!("{}: {}", level.name(), verdict));
+            }
+            if needs_fix && fixable {
+                ui.label(format!("Click to adjust the text luminance to {}", self.target.name()));
+            } else if needs_fix {
+                ui.label("The text color is derived from another cell, fix that one instead");
+            }
+        });
+
+        fixed
+    }
+
+    // Colors are drawn as seen with `view`, if any, ratios are always those of the colors themselves.
+    pub fn place(&mut self, ui: &mut Ui, map: &MapData, view: Option<Deficiency>, chan: &mut Chan) {
+        let cells = map.cells();
+        if cells.len() < 2 {
+            ui.label("Add at least two colors to compare their contrast.");
+            return;
+        }
+
+        egui::ComboBox::from_label("fix target")
+            .selected_text(self.target.name())
+            .show_ui(ui, |ui| {
+                for level in ContrastLevel::ALL {
+                    ui.selectable_value(&mut self.target, level, level.name());
+                }
+            });
+
+        egui::ScrollArea::both().max_height(Self::MAX_HEIGHT).show(ui, |ui| {
+            egui::Grid::new("contrast-matrix").show(ui, |ui| {
+                ui.label("text \\ background");
+                for &(_, index, color) in &cells {
+                    ui.add(ColorLabel::new().construct(color.seen_as(view), map.label_of(index),
+                                                       Self::TILE_WIDTH, Self::TILE_HEIGHT));
+                }
+                ui.end_row();
+
+                for &(row_loc, row_index, text) in &cells {
+                    ui.add(ColorLabel::new().construct(text.seen_as(view), map.label_of(row_index),
+                                                       Self::TILE_WIDTH, Self::TILE_HEIGHT));
+                    let fixable = map.link_at(row_loc).is_none();
+                    for &(col_loc, _, background) in &cells {
+                        if row_loc == col_loc {
+                            let (rect, _) = ui.allocate_exact_size(vec2(Self::TILE_WIDTH, Self::TILE_HEIGHT),
+                                                                   Sense::hover());
+                            ui.painter().text(rect.center(), Align2::CENTER_CENTER, "–",
+                                              FontId::proportional(Self::TILE_HEIGHT * 0.3),
+                                              ui.visuals().weak_text_color());
+                        } else if let Some(fixed) = self.tile(ui, text, background, fixable, view) {
+                            chan.push(Message::Recolor { index: row_index, to: fixed });
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+    }
+}
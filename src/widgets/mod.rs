@@ -2,9 +2,16 @@ mod shade_strip;
 mod three_strip;
 mod color_map;
 mod color_label;
+mod color_names;
+mod contrast_matrix;
+mod vision_report;
+mod color_plane;
 
 pub use shade_strip::ShadeStrip;
 pub use three_strip::ThreeStrip;
 pub use color_map::ColorMap;
 pub use color_label::ColorLabel;
+pub use contrast_matrix::ContrastMatrix;
+pub use vision_report::VisionReport;
+pub use color_plane::ColorPlane;
 
@@ -1,16 +1,24 @@
-use egui::{Color32, Rounding, Ui, vec2, Sense, Widget, pos2, Stroke, Rect, FontId, Layout};
-use crate::{util::{color::{Color, ColorDB, shades, Lerp}, RoundingLegend, RoundedRect, }, state::{Chan, Message}};
+use egui::{Color32, Rounding, Ui, vec2, Sense, Widget, pos2, Stroke, Rect, Painter, Shape};
+use crate::{util::{color::{Color, ColorDB, shades, Lerp}, vision::Deficiency, RoundingLegend, RoundedRect, tesselate, 
+                   CHECKER_COLORS}, 
+            state::{Chan, Message}};
+
+use super::color_names::{nearest_name, candidates_tooltip};
 
 pub struct ShadeStrip {
     last_color: Color,
     last_index: usize,
-    shades: Vec<(Color, Color32)>,
+    shades: Vec<Color>,
 
     new_color: Color,
     max_shade_count: usize,
     lerp: Lerp,
     rounding_legend: RoundingLegend,
     show_hex: bool,
+    // shades are drawn as seen with it, if any
+    view: Option<Deficiency>,
+    // nearest name of the base color, with the color and database size it was looked up for
+    name: Option<(Color, usize, String)>,
 }
 
 impl ShadeStrip {
@@ -19,9 +27,12 @@ impl ShadeStrip {
     const DOT_RADIUS_FRAC: f32 = 0.01;
     const SELECT_WIDTH: f32 = 2.0;
     const OUTLINE_WIDTH: f32 = 2.0;
+    // side of the corner flag on out of gamut shades, to the shade height
+    const GAMUT_MARK_FRAC: f32 = 0.25;
+    const CHECKER_LEVEL: usize = 4;
 
     pub fn new(color_ref: &Color, show_hex: bool, 
-               lerp: Lerp, rounding_legend: RoundingLegend) -> Self {
+               lerp: Lerp, rounding_legend: RoundingLegend, view: Option<Deficiency>) -> Self {
         let color = *color_ref;
         ShadeStrip { 
             last_color: color,
@@ -32,11 +43,42 @@ impl ShadeStrip {
             max_shade_count: 10,
             lerp,
             rounding_legend,
-            show_hex
+            show_hex,
+            view,
+            name: None,
+        }
+    }
+
+    // The hex label, followed by the nearest name once the database is there.
+    fn label(&mut self, db: Option<&ColorDB>, color: Color) -> String {
+        let Some(db) = db else { return color.to_hex() };
+        if self.name.as_ref().is_none_or(|(named, len, _)| *named != color || *len != db.len()) {
+            self.name = Some((color, db.len(), nearest_name(db, color)));
         }
+        format!("{}  {}", color.to_hex(), self.name.as_ref().unwrap().2)
     }
 
-    pub fn construct<'a>(&'a mut self, base_color: Color, chan: &'a mut Chan, 
+    // Flags the top right corner of a color that sRGB cannot show, so that it is known to be
+    // drawn gamut mapped.
+    fn mark_gamut(painter: &Painter, rect: Rect, color: Color) {
+        if color.in_gamut() {
+            return;
+        }
+        let side = rect.height().min(rect.width()) * Self::GAMUT_MARK_FRAC;
+        let corner = rect.right_top();
+        let flag = vec![corner, corner - vec2(side, 0.0), corner + vec2(0.0, side)];
+        painter.add(Shape::convex_polygon(flag, color.borw(), Stroke::none()));
+    }
+
+    // Translucent colors are painted over a checkerboard.
+    fn fill(painter: &Painter, rr: RoundedRect, color: Color, view: Option<Deficiency>) {
+        if color.alpha < 1.0 {
+            tesselate(painter, rr, Self::CHECKER_LEVEL, CHECKER_COLORS);
+        }
+        painter.rect_filled(rr.rect, rr.rounding, color.to_color32_as(view));
+    }
+
+    pub fn construct<'a>(&'a mut self, base_color: Color, db: Option<&'a ColorDB>, chan: &'a mut Chan, 
                          max_width: f32, max_height: f32, disabled: bool) -> impl Widget + 'a {
         move |ui: &mut Ui| -> egui::Response {
 
@@ -60,13 +102,13 @@ impl ShadeStrip {
                 } else {
                     let (shades, last_index) = shades(base_color, self.max_shade_count, &self.lerp);
                     self.last_index = last_index;
-                    self.shades = shades.iter().map(|s| (*s, s.to_color32())).collect();
+                    self.shades = shades;
                     (&self.shades, self.last_index)
                 };
 
                 let each_width = rect.width()/(shades.len() as f32);
 
-                for (i, (shade, shade_color32)) in shades.iter().enumerate() {
+                for (i, shade) in shades.iter().enumerate() {
                     let shade_rect_x = rect.left() + each_width * (i as f32);
                     let shade_rect = Rect::from_min_size(pos2(shade_rect_x, rect.top()), vec2(each_width, rect.height()));
 
@@ -78,22 +120,26 @@ impl ShadeStrip {
                         Rounding::default()
                     };
 
-                    painter.rect_filled(shade_rect, rounding, *shade_color32);
+                    Self::fill(painter, (shade_rect, rounding).into(), *shade, self.view);
+                    Self::mark_gamut(painter, shade_rect, *shade);
 
                     let dot_radius = width * ShadeStrip::DOT_RADIUS_FRAC;
 
                     let is_inside = response.ctx.pointer_interact_pos()
-                        .map_or(false, |pos| shade_rect.contains(pos));
+                        .is_some_and(|pos| shade_rect.contains(pos));
                     if is_inside {
                         self.new_color = *shade; 
+                        if let Some(db) = db.filter(|_| self.show_hex) {
+                            candidates_tooltip(&response.ctx, id.with("names"), db, *shade, self.view);
+                        }
                         painter.circle_stroke(shade_rect.center(), dot_radius, 
                                               Stroke{width: Self::SELECT_WIDTH,
-                                                     color: shade.accent()
+                                                     color: shade.seen_as(self.view).accent()
                                               });
                     }
 
                     if i == index {
-                        painter.circle_filled(shade_rect.center(), dot_radius , shade.accent());
+                        painter.circle_filled(shade_rect.center(), dot_radius , shade.seen_as(self.view).accent());
                     }
                 }
             } 
@@ -113,11 +159,15 @@ impl ShadeStrip {
                     base_color
                 };
 
-                painter.rect_filled(rect, rounding, draw_color.to_color32());
+                Self::fill(painter, (rect, rounding).into(), draw_color, self.view);
+                if !disabled {
+                    Self::mark_gamut(painter, rect, base_color);
+                }
 
                 if self.show_hex {
                     let rr: RoundedRect = (rect, rounding).into();
-                    rr.label_inset(painter, base_color.to_hex(), base_color.accent_color(), None);
+                    let label = self.label(db, base_color);
+                    rr.label_fit(painter, label, base_color.seen_as(self.view).accent_color(), None);
                 }
             }
             
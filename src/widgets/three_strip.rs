@@ -1,37 +1,82 @@
 use std::time::{SystemTime, Duration};
 
-use egui::{Ui, InnerResponse};
+use egui::{Ui, InnerResponse, Sense, vec2};
 
-use crate::{util::{color::{Color, hue_lerp, chroma_lerp, luminance_lerp}, RoundingLegend}, state::{Chan, Message}};
+use crate::{util::{color::{Color, ColorDB, hue_lerp, chroma_lerp, luminance_lerp, alpha_lerp}, name_index::NameIndex, 
+                   space::WorkingSpace, vision::Deficiency, RoundingLegend}, 
+            state::{Chan, Message}};
 
-use super::ShadeStrip;
+use super::{ShadeStrip, ColorPlane};
 
 pub struct ThreeStrip {
-    axis: [ShadeStrip; 3],
+    axis: [ShadeStrip; 4],
+    space: WorkingSpace,
+    view: Option<Deficiency>,
+    plane: ColorPlane,
     entered: bool,
-    first_shown: Option<SystemTime>
+    first_shown: Option<SystemTime>,
+    search: String,
+    // what is typed in the color field, following the color while it is not focused
+    color_input: String,
+    // built once the database has loaded
+    names: Option<NameIndex>,
 }
 
 impl ThreeStrip {
-    const ROUNDING_LEGEND: [RoundingLegend; 3] = [RoundingLegend::new(1, 0, 1, 0),
+    const ROUNDING_LEGEND: [RoundingLegend; 4] = [RoundingLegend::new(1, 0, 1, 0),
+                                                   RoundingLegend::new(0, 0, 0, 0),
                                                    RoundingLegend::new(0, 0, 0, 0),
                                                    RoundingLegend::new(0, 1, 0, 1)];
 
     const GAP_REMOVE: f32 = 3f32;
     const IDLE_TIME: u64 = 1000;
+    const SEARCH_RESULTS: usize = 8;
+    const FIELD_WIDTH: f32 = 48.0;
+    // displayed range of the luminance, chroma and hue fields
+    const COMPONENT_SCALE: [f32; 3] = [100.0, 100.0, 360.0];
+    const COMPONENT_MAX: [f32; 3] = [100.0, 150.0, 360.0];
+
+    // Hue, luminance, chroma and opacity.
+    fn strips(color: &Color, space: WorkingSpace, view: Option<Deficiency>) -> [ShadeStrip; 4] {
+        [
+            ShadeStrip::new(color, false, hue_lerp(space, 0.0, 1.0), 
+                            ThreeStrip::ROUNDING_LEGEND[0], view),
+            ShadeStrip::new(color, true, luminance_lerp(space, 0.0, 1.0), 
+                            ThreeStrip::ROUNDING_LEGEND[1], view),
+            ShadeStrip::new(color, false, chroma_lerp(space, 0.0, 1.0), 
+                            ThreeStrip::ROUNDING_LEGEND[2], view),
+            ShadeStrip::new(color, false, alpha_lerp(0.0, 1.0), 
+                            ThreeStrip::ROUNDING_LEGEND[3], view)
+        ]
+    }
 
-    pub fn new(color: &Color) -> Self {
+    pub fn new(color: &Color, space: WorkingSpace) -> Self {
         ThreeStrip{
-            axis: [
-                ShadeStrip::new(color, false, hue_lerp(0.0, 1.0), 
-                                ThreeStrip::ROUNDING_LEGEND[0]),
-                ShadeStrip::new(color, true, luminance_lerp(0.0, 1.0), 
-                                ThreeStrip::ROUNDING_LEGEND[1]),
-                ShadeStrip::new(color, false, chroma_lerp(0.0, 1.0), 
-                                ThreeStrip::ROUNDING_LEGEND[2])
-            ],
+            axis: ThreeStrip::strips(color, space, None),
+            space,
+            view: None,
+            plane: ColorPlane::new(),
             entered: false,
-            first_shown: None
+            first_shown: None,
+            search: String::new(),
+            color_input: String::new(),
+            names: None,
+        }
+    }
+
+    // Shades are taken along the axes of `space` from then on.
+    pub fn set_space(&mut self, color: &Color, space: WorkingSpace) {
+        if space != self.space {
+            self.axis = ThreeStrip::strips(color, space, self.view);
+            self.space = space;
+        }
+    }
+
+    // Colors are drawn as seen with `view` from then on, if any.
+    pub fn set_view(&mut self, color: &Color, view: Option<Deficiency>) {
+        if view != self.view {
+            self.axis = ThreeStrip::strips(color, self.space, view);
+            self.view = view;
         }
     }
 
@@ -42,13 +87,102 @@ impl ThreeStrip {
         } else { false }
     }
     
-    pub fn place(&mut self, ui: &mut Ui, color: Color, chan: &mut Chan, 
-                 max_width: f32, max_height: f32) -> InnerResponse<()> {
-        let resp = ui.vertical(|ui| {
-            for i in 0..3 {
-                ui.add(self.axis[i].construct(color, chan, max_width, max_height/3.0, false));
-                ui.add_space(-Self::GAP_REMOVE)
+    // Exact values for the color: a field taking any CSS color, and its components in the working
+    // space and in sRGB. Every valid edit changes the color.
+    fn value_fields(&mut self, ui: &mut Ui, color: Color, chan: &mut Chan, width: f32) {
+        ui.add_space(Self::GAP_REMOVE * 2.0);
+
+        let parsed = self.color_input.parse::<Color>();
+        let invalid = parsed.is_err().then(|| ui.visuals().error_fg_color);
+        let mut response = ui.add(egui::TextEdit::singleline(&mut self.color_input)
+                                  .hint_text("#rrggbb")
+                                  .text_color_opt(invalid)
+                                  .desired_width(width));
+        if let Err(err) = &parsed {
+            response = response.on_hover_text(err.to_string());
+        }
+        if response.changed() {
+            match self.color_input.parse::<Color>() {
+                Ok(to) if to != color => chan.push(Message::ChangeColor { to }),
+                _ => (),
+            }
+        }
+        if !response.has_focus() {
+            self.color_input = color.to_hex();
+        }
+
+        ui.horizontal(|ui| {
+            let mut components = self.space.components(color);
+            let mut changed = false;
+            for (i, axis) in self.space.axes().into_iter().enumerate() {
+                let mut value = components[i] * Self::COMPONENT_SCALE[i];
+                ui.label(axis);
+                changed |= ui.add_sized([Self::FIELD_WIDTH, ui.spacing().interact_size.y],
+                                        egui::DragValue::new(&mut value)
+                                            .clamp_range(0.0..=Self::COMPONENT_MAX[i])
+                                            .max_decimals(1))
+                    .changed();
+                components[i] = value / Self::COMPONENT_SCALE[i];
             }
+            if changed {
+                chan.push(Message::ChangeColor { to: Color { alpha: color.alpha, ..self.space.color(components) } });
+            }
+        });
+
+        ui.horizontal(|ui| {
+            let mut rgb = color.to_rgb();
+            let mut changed = false;
+            for (channel, axis) in rgb.iter_mut().zip(["R", "G", "B"]) {
+                ui.label(axis);
+                changed |= ui.add_sized([Self::FIELD_WIDTH, ui.spacing().interact_size.y], 
+                                        egui::DragValue::new(channel))
+                    .changed();
+            }
+            if changed {
+                let [r, g, b] = rgb;
+                chan.push(Message::ChangeColor { to: Color { alpha: color.alpha, ..Color::from_rgb(r, g, b) } });
+            }
+        });
+    }
+
+    // A name search field, with the matches listed below it; picking one changes the color.
+    fn search_names(&mut self, ui: &mut Ui, color: Color, db: Option<&ColorDB>, chan: &mut Chan, width: f32) {
+        // rebuilt when dictionaries are added
+        if self.names.as_ref().map(NameIndex::len) != db.map(ColorDB::len) {
+            self.names = db.map(NameIndex::new);
+        }
+        let Some(names) = &self.names else { return };
+
+        ui.add_space(Self::GAP_REMOVE * 2.0);
+        ui.add(egui::TextEdit::singleline(&mut self.search).hint_text("search names").desired_width(width));
+
+        for found in names.search(&self.search, Self::SEARCH_RESULTS) {
+            ui.horizontal(|ui| {
+                let size = ui.spacing().interact_size.y;
+                let (rect, _) = ui.allocate_exact_size(vec2(size, size), Sense::hover());
+                ui.painter().rect_filled(rect, size * 0.2, found.color.to_color32_as(self.view));
+                if ui.selectable_label(false, found.name).clicked() {
+                    chan.push(Message::ChangeColor { to: Color { alpha: color.alpha, ..found.color } });
+                }
+                ui.weak(found.source);
+            });
+        }
+    }
+
+    pub fn place(&mut self, ui: &mut Ui, color: Color, db: Option<&ColorDB>, chan: &mut Chan, 
+                 max_width: f32, max_height: f32) -> InnerResponse<()> {
+        let resp = ui.horizontal_top(|ui| {
+            ui.vertical(|ui| {
+                let count = self.axis.len();
+                for strip in &mut self.axis {
+                    ui.add(strip.construct(color, db, chan, max_width, max_height/count as f32, false));
+                    ui.add_space(-Self::GAP_REMOVE)
+                }
+                // inside the strips' area, so that using them does not count as leaving the picker
+                self.value_fields(ui, color, chan, max_width);
+                self.search_names(ui, color, db, chan, max_width);
+            });
+            ui.vertical(|ui| self.plane.place(ui, color, self.space, self.view, chan, max_width, max_height));
         });
 
         if self.first_shown.is_none() {
@@ -0,0 +1,60 @@
+/*
+ * Picks the color vision deficiency the interface is drawn with, and lists the palette cells
+ * that can be told apart with normal vision but not with each deficiency.
+ * */
+
+use egui::{Ui, RichText};
+
+use crate::{state::MapData, util::{color::Color, vision::{Deficiency, confusions}}};
+
+pub struct VisionReport {}
+
+impl VisionReport {
+    pub fn new() -> Self {
+        VisionReport {}
+    }
+
+    fn view_name(view: Option<Deficiency>) -> &'static str {
+        view.map_or("normal vision", Deficiency::name)
+    }
+
+    // A swatch of the color, drawn as seen with `view`.
+    fn swatch(ui: &mut Ui, color: Color, view: Option<Deficiency>) {
+        ui.label(RichText::new("■").color(color.to_color32_as(view)));
+    }
+
+    pub fn place(&mut self, ui: &mut Ui, map: &MapData, view: &mut Option<Deficiency>) {
+        egui::ComboBox::from_label("view as")
+            .selected_text(Self::view_name(*view))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(view, None, Self::view_name(None));
+                for deficiency in Deficiency::ALL {
+                    ui.selectable_value(view, Some(deficiency), deficiency.name());
+                }
+            });
+
+        let cells = map.cells();
+        let colors: Vec<Color> = cells.iter().map(|&(_, _, color)| color).collect();
+
+        egui::Grid::new("vision-report").show(ui, |ui| {
+            for deficiency in Deficiency::ALL {
+                ui.label(deficiency.name());
+                let found = confusions(&colors, deficiency);
+                if found.is_empty() {
+                    ui.label(RichText::new("no colors confused").weak());
+                } else {
+                    ui.horizontal_wrapped(|ui| {
+                        for (i, j, delta) in found {
+                            let ((_, a, color_a), (_, b, color_b)) = (cells[i], cells[j]);
+                            Self::swatch(ui, color_a, *view);
+                            Self::swatch(ui, color_b, *view);
+                            ui.label(format!("{} & {}", map.label_of(a), map.label_of(b)))
+                                .on_hover_text(format!("ΔE {:.1} with {}", delta, deficiency.name()));
+                        }
+                    });
+                }
+                ui.end_row();
+            }
+        });
+    }
+}